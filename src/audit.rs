@@ -0,0 +1,103 @@
+use std::time::SystemTime;
+
+use kube::ResourceExt;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{correlation, elasticsearch::ElasticsearchApi, ElasticsearchUser};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Mutations performed by the operator in Elasticsearch.
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    RoleCreated,
+    RoleUpdated,
+    RoleDeleted,
+    UserCreated,
+    UserUpdated,
+    PasswordUpdated,
+    UserDeleted,
+    UserDisabled,
+    ApiKeyCreated,
+    ApiKeyInvalidated,
+    IndexCreated,
+    RoleMappingUpdated,
+    RoleMappingDeleted,
+}
+
+#[derive(Serialize)]
+struct AuditDocument<'a> {
+    #[serde(rename = "@timestamp")]
+    timestamp: String,
+    action: AuditAction,
+    /// Name of the affected Elasticsearch user or role.
+    target: &'a str,
+    resource: AuditResource,
+    summary: &'a str,
+    /// Definition before the change, for updates and deletions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<serde_json::Value>,
+    operator: String,
+    /// Matches the X-Opaque-Id of the requests of the reconcile.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AuditResource {
+    namespace: String,
+    name: String,
+    uid: String,
+}
+
+/// Writes an audit document per mutation into an optional
+/// Elasticsearch index.
+pub struct AuditLog {
+    index: Option<String>,
+}
+
+impl AuditLog {
+    pub fn new(index: Option<String>) -> Self {
+        Self { index }
+    }
+    /// Record a mutation. Failing to write the document is
+    /// only logged, the mutation already happened.
+    /// `previous` is the definition before an update or deletion,
+    /// allowing to reconstruct it. Without audit index it is logged.
+    pub(crate) async fn record(
+        &self,
+        elastic: &impl ElasticsearchApi,
+        user: &ElasticsearchUser,
+        action: AuditAction,
+        target: &str,
+        summary: &str,
+        previous: Option<serde_json::Value>,
+    ) {
+        let Some(index) = &self.index else {
+            if let Some(previous) = previous {
+                info!("Previous definition of {}: {}", target, previous);
+            }
+            return;
+        };
+        let document = AuditDocument {
+            timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            action,
+            target,
+            resource: AuditResource {
+                namespace: user.namespace().unwrap_or_default(),
+                name: user.name_any(),
+                uid: user.uid().unwrap_or_default(),
+            },
+            summary,
+            previous,
+            operator: format!("ext-elasticsearch-operator/{}", VERSION),
+            correlation_id: correlation::current(),
+        };
+        let document = serde_json::to_value(&document).expect("Serde JSON failed to serialize");
+        if let Err(e) = elastic.index_document(index, document).await {
+            warn!("Could not write audit document to {}: {}", index, e);
+        }
+    }
+}
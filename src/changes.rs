@@ -0,0 +1,48 @@
+use std::fmt::Display;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A field changed by the operator in Elasticsearch. Secret
+/// values are masked, e.g. of the password.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// e.g. user.roles or role.indices
+    pub field: String,
+    /// Unset if the field was not set before.
+    pub old: Option<String>,
+    /// Unset if the field got removed.
+    pub new: Option<String>,
+}
+
+impl Change {
+    pub fn new(field: &str, old: Option<impl Display>, new: Option<impl Display>) -> Self {
+        Self {
+            field: field.to_string(),
+            old: old.map(|v| v.to_string()),
+            new: new.map(|v| v.to_string()),
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{} {} => {}]",
+            self.field,
+            self.old.as_deref().unwrap_or("<undefined>"),
+            self.new.as_deref().unwrap_or("<undefined>")
+        )
+    }
+}
+
+/// Changes for logs and Events, e.g. "[user.email a => b] [user.roles ...]".
+pub fn describe(changes: &[Change]) -> String {
+    changes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
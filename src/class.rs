@@ -0,0 +1,187 @@
+use kube::{
+    runtime::reflector::{ObjectRef, Store},
+    Api, Client, ResourceExt,
+};
+use kube_derive::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::OperatorError, CleanupMode, ElasticsearchUser, SecretFormat, UserPermissions,
+    PASSWORD_LENGTH,
+};
+
+/// Bounds of password.length of a class.
+const MIN_PASSWORD_LENGTH: usize = 16;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Defaults and policy for the ElasticsearchUsers referencing it by
+/// spec.classRef, maintained by platform teams. Fields set in the
+/// ElasticsearchUser take precedence, besides the password policy.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "eeops.io",
+    version = "v1",
+    kind = "ElasticsearchUserClass",
+    shortname = "esuserclass",
+    shortname = "esuc",
+    category = "elastic"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ElasticsearchUserClassSpec {
+    /// Permissions of ElasticsearchUsers without spec.permissions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<UserPermissions>,
+    /// Secret format of ElasticsearchUsers without spec.secretFormat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_format: Option<SecretFormat>,
+    /// Cleanup mode of ElasticsearchUsers without spec.cleanup,
+    /// instead of the operator's CLEANUP_MODE.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cleanup: Option<CleanupMode>,
+    /// Generated passwords of all ElasticsearchUsers of the class.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<PasswordPolicy>,
+}
+
+/// How passwords are generated.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordPolicy {
+    /// Between 16 and 128, defaults to 24.
+    #[serde(default = "default_password_length")]
+    pub length: usize,
+    /// Include symbols, e.g. for policies requiring them.
+    #[serde(default)]
+    pub symbols: bool,
+}
+
+fn default_password_length() -> usize {
+    PASSWORD_LENGTH
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            length: PASSWORD_LENGTH,
+            symbols: false,
+        }
+    }
+}
+
+/// The ElasticsearchUser with the defaults of its class filled in.
+/// Fails if spec.classRef names a class which does not exist.
+pub fn apply_class(
+    user: &ElasticsearchUser,
+    class: Option<&ElasticsearchUserClass>,
+) -> Result<ElasticsearchUser, OperatorError> {
+    let mut user = user.clone();
+    let Some(class_ref) = &user.spec.class_ref else {
+        return Ok(user);
+    };
+    let class = class.ok_or_else(|| {
+        OperatorError::Validation(format!("ElasticsearchUserClass {} not found", class_ref))
+    })?;
+    if let Some(password) = &class.spec.password {
+        if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&password.length) {
+            return Err(OperatorError::Validation(format!(
+                "ElasticsearchUserClass {}: password length must be between {} and {}",
+                class_ref, MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+            )));
+        }
+    }
+    let spec = &mut user.spec;
+    spec.permissions = spec.permissions.or(class.spec.permissions);
+    spec.secret_format = spec.secret_format.or(class.spec.secret_format);
+    spec.cleanup = spec.cleanup.or(class.spec.cleanup);
+    spec.password_policy = class.spec.password.clone();
+    Ok(user)
+}
+
+/// Class of spec.classRef in the cache, if any.
+pub fn cached_class(
+    user: &ElasticsearchUser,
+    classes: &Store<ElasticsearchUserClass>,
+) -> Option<ElasticsearchUserClass> {
+    let class_ref = user.spec.class_ref.as_ref()?;
+    classes
+        .get(&ObjectRef::new(class_ref))
+        .map(|class| class.as_ref().clone())
+}
+
+/// Class of spec.classRef, for commands without cache.
+pub async fn get_class(
+    user: &ElasticsearchUser,
+    client: &Client,
+) -> Result<Option<ElasticsearchUserClass>, OperatorError> {
+    let Some(class_ref) = &user.spec.class_ref else {
+        return Ok(None);
+    };
+    let classes: Api<ElasticsearchUserClass> = Api::all(client.clone());
+    Ok(classes.get_opt(class_ref).await?)
+}
+
+/// ElasticsearchUsers to reconcile when a class changes.
+pub fn users_of_class(
+    class: &ElasticsearchUserClass,
+    users: &Store<ElasticsearchUser>,
+) -> Vec<ObjectRef<ElasticsearchUser>> {
+    users
+        .state()
+        .iter()
+        .filter(|user| user.spec.class_ref.as_deref() == Some(class.name_any().as_str()))
+        .map(|user| ObjectRef::from_obj(user.as_ref()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn class(spec: serde_json::Value) -> ElasticsearchUserClass {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "eeops.io/v1",
+            "kind": "ElasticsearchUserClass",
+            "metadata": {"name": "standard"},
+            "spec": spec,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn class_defaults_unset_fields() {
+        let class = class(serde_json::json!({
+            "permissions": "Write",
+            "secretFormat": "beats",
+            "cleanup": "Disable",
+            "password": {"length": 32},
+        }));
+        let user = fixtures::user(
+            "app",
+            serde_json::json!({"spec": {
+                "prefixes": ["app-"], "permissions": null,
+                "classRef": "standard", "cleanup": "Delete",
+            }}),
+        );
+        let applied = apply_class(&user, Some(&class)).unwrap();
+        assert!(matches!(
+            applied.spec.permissions,
+            Some(UserPermissions::Write)
+        ));
+        assert_eq!(applied.spec.secret_format(), SecretFormat::Beats);
+        // Set by the ElasticsearchUser
+        assert_eq!(applied.spec.cleanup, Some(CleanupMode::Delete));
+        assert_eq!(
+            applied.spec.password_policy,
+            Some(PasswordPolicy {
+                length: 32,
+                symbols: false
+            })
+        );
+
+        assert!(apply_class(&user, None).is_err());
+        let weak = self::class(serde_json::json!({"password": {"length": 8}}));
+        assert!(apply_class(&user, Some(&weak)).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+/// Parsed command line.
+pub struct Args {
+    /// Path given by `--config <path>`, `--config=<path>` or EEOPS_CONFIG.
+    pub config: Option<String>,
+    /// Run against an in-process fake of the Elasticsearch security API.
+    pub mock_elastic: bool,
+    /// Context of the kubeconfig, given by `--kube-context <name>` or
+    /// KUBE_CONTEXT. In-cluster or the current context if unset.
+    pub kube_context: Option<String>,
+    /// Service account bound by generate-rbac.
+    pub service_account: String,
+    pub command: Command,
+}
+
+pub enum Command {
+    /// Run the operator, the default.
+    Run,
+    /// Print a drift report of CRs against Elasticsearch and exit.
+    Status,
+    /// Install or update the CRDs and exit.
+    InstallCrds,
+    /// Print the RBAC resources required by the configuration.
+    GenerateRbac,
+}
+
+pub const USAGE: &str =
+    "Usage: ext-elasticsearch-operator [--config <path>] [--mock-elastic] [COMMAND]
+
+Options:
+  --config <path>  YAML configuration file, environment variables take precedence
+  --mock-elastic   Use an in-memory fake of Elasticsearch, for local development
+  --kube-context <name>
+                   Context of the kubeconfig (KUBECONFIG or ~/.kube/config) to run against
+  --service-account <name>
+                   Service account bound by generate-rbac [default: eeop-ext-elasticsearch-operator]
+
+Commands:
+  run           Run the operator (default)
+  status        Compare ElasticsearchUsers with Elasticsearch and print a drift report
+  install-crds  Install or update the CRDs and exit, needs no Elasticsearch
+  generate-rbac Print the Role, ClusterRoles and bindings the configuration requires";
+
+pub fn parse_args() -> Result<Args, String> {
+    let mut config = None;
+    let mut mock_elastic = false;
+    let mut service_account = None;
+    let mut kube_context = None;
+    let mut command = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            config = Some(args.next().ok_or("--config requires a path")?);
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            config = Some(path.to_string());
+        } else if arg == "--kube-context" {
+            kube_context = Some(args.next().ok_or("--kube-context requires a name")?);
+        } else if let Some(name) = arg.strip_prefix("--kube-context=") {
+            kube_context = Some(name.to_string());
+        } else if arg == "--service-account" {
+            service_account = Some(args.next().ok_or("--service-account requires a name")?);
+        } else if let Some(name) = arg.strip_prefix("--service-account=") {
+            service_account = Some(name.to_string());
+        } else if arg == "--mock-elastic" {
+            mock_elastic = true;
+        } else if command.is_none() && !arg.starts_with('-') {
+            command = Some(match arg.as_str() {
+                "run" => Command::Run,
+                "status" => Command::Status,
+                "install-crds" => Command::InstallCrds,
+                "generate-rbac" => Command::GenerateRbac,
+                other => return Err(format!("Unknown command \"{}\"", other)),
+            });
+        } else {
+            return Err(format!("Unexpected argument \"{}\"", arg));
+        }
+    }
+    Ok(Args {
+        config: config.or(std::env::var("EEOPS_CONFIG").ok()),
+        mock_elastic,
+        kube_context: kube_context.or(std::env::var("KUBE_CONTEXT").ok()),
+        service_account: service_account
+            .unwrap_or_else(|| "eeop-ext-elasticsearch-operator".to_string()),
+        command: command.unwrap_or(Command::Run),
+    })
+}
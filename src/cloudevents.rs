@@ -0,0 +1,119 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use kube::ResourceExt;
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::ElasticsearchUser;
+
+const SOURCE: &str = "ext-elasticsearch-operator";
+
+/// Lifecycle changes of Elasticsearch users.
+#[derive(Clone, Copy, Debug)]
+pub enum LifecycleEvent {
+    UserCreated,
+    PasswordRotated,
+    UserDeleted,
+    UserDisabled,
+}
+
+impl LifecycleEvent {
+    fn type_name(&self) -> &'static str {
+        match self {
+            LifecycleEvent::UserCreated => "io.eeops.user.created",
+            LifecycleEvent::PasswordRotated => "io.eeops.user.password_rotated",
+            LifecycleEvent::UserDeleted => "io.eeops.user.deleted",
+            LifecycleEvent::UserDisabled => "io.eeops.user.disabled",
+        }
+    }
+}
+
+/// CloudEvent in structured content mode (JSON format, spec 1.0).
+#[derive(Serialize)]
+struct CloudEvent<'a> {
+    specversion: &'static str,
+    id: String,
+    source: &'static str,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    subject: String,
+    time: String,
+    datacontenttype: &'static str,
+    data: EventData<'a>,
+}
+
+#[derive(Serialize)]
+struct EventData<'a> {
+    name: String,
+    namespace: String,
+    username: &'a str,
+    cluster: &'a str,
+}
+
+/// Sends CloudEvents to an optional HTTP sink.
+pub struct CloudEventSink {
+    url: Option<String>,
+    client: reqwest::Client,
+    cluster: String,
+}
+
+impl CloudEventSink {
+    pub fn new(url: Option<String>, cluster: impl ToString) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_millis(5_000))
+                .build()
+                .expect("Unexpected error in building HTTP Client"),
+            cluster: cluster.to_string(),
+        }
+    }
+    /// Emit a lifecycle event. Delivery failures are only logged.
+    pub(crate) async fn emit(&self, event: LifecycleEvent, user: &ElasticsearchUser) {
+        let Some(url) = &self.url else {
+            return;
+        };
+        let now = SystemTime::now();
+        let namespace = user.namespace().unwrap_or_default();
+        let cloud_event = CloudEvent {
+            specversion: "1.0",
+            id: format!(
+                "{}-{}",
+                user.uid().unwrap_or_default(),
+                now.duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos()
+            ),
+            source: SOURCE,
+            type_: event.type_name(),
+            subject: format!("{}/{}", namespace, user.name_any()),
+            time: humantime::format_rfc3339_seconds(now).to_string(),
+            datacontenttype: "application/json",
+            data: EventData {
+                name: user.name_any(),
+                namespace,
+                username: user.username(),
+                cluster: &self.cluster,
+            },
+        };
+        let body = serde_json::to_vec(&cloud_event).expect("Serde JSON failed to serialize event");
+        let res = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/cloudevents+json")
+            .body(body)
+            .send()
+            .await;
+        match res {
+            Ok(res) if res.status().is_success() => {
+                debug!("Emitted {} for {}", event.type_name(), user.name_any())
+            }
+            Ok(res) => warn!(
+                "CloudEvents sink responded to {} with {}",
+                event.type_name(),
+                res.status()
+            ),
+            Err(e) => warn!("Could not emit {}: {}", event.type_name(), e),
+        }
+    }
+}
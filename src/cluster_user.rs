@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::{controller::Action, watcher, Controller},
+    Api, Client, Resource, ResourceExt,
+};
+use kube_derive::CustomResource;
+use log::{debug, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::OperatorError, field_manager, ElasticsearchUser, ElasticsearchUserSpec, REQUEUE_SECONDS,
+};
+
+/// Set on the ElasticsearchUser of a ClusterElasticsearchUser to its name.
+pub const CLUSTER_USER_LABEL: &str = "eeops.io/cluster-user";
+/// Prefix of the names of the ElasticsearchUsers.
+const NAME_PREFIX: &str = "cluster-";
+
+/// Platform-level account not belonging to a single team namespace,
+/// e.g. a shared ingest user. Same spec as ElasticsearchUser, but
+/// secretNamespace is required.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "eeops.io",
+    version = "v1",
+    kind = "ClusterElasticsearchUser",
+    shortname = "clusteresuser",
+    shortname = "cesu",
+    category = "elastic"
+)]
+#[kube(status = "ClusterElasticsearchUserStatus")]
+pub struct ClusterElasticsearchUserSpec {
+    #[serde(flatten)]
+    user: ElasticsearchUserSpec,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterElasticsearchUserStatus {
+    /// Status ok of the ElasticsearchUser.
+    pub ok: bool,
+    pub error_message: Option<String>,
+    pub username: Option<String>,
+    /// The ElasticsearchUser reconciling it, as namespace/name.
+    pub elasticsearch_user: Option<String>,
+}
+
+/// ElasticsearchUser in the operator's namespace, owned by the
+/// ClusterElasticsearchUser, with the secret in secretNamespace.
+fn namespaced_user(
+    cluster_user: &ClusterElasticsearchUser,
+) -> Result<ElasticsearchUser, OperatorError> {
+    if cluster_user.spec.user.secret_namespace.is_none() {
+        return Err(OperatorError::Validation(
+            "secretNamespace is required for ClusterElasticsearchUsers".to_string(),
+        ));
+    }
+    let name = format!("{}{}", NAME_PREFIX, cluster_user.name_any());
+    let mut user = ElasticsearchUser::new(&name, cluster_user.spec.user.clone());
+    let mut labels: BTreeMap<String, String> = cluster_user.labels().clone();
+    labels.insert(CLUSTER_USER_LABEL.to_string(), cluster_user.name_any());
+    user.metadata.labels = Some(labels);
+    // Only those of the operator, e.g. eeops.io/protected
+    let annotations = cluster_user
+        .annotations()
+        .iter()
+        .filter(|(key, _)| key.starts_with("eeops.io/"))
+        .map(|(key, value)| (key.clone(), value.clone()));
+    user.metadata.annotations = Some(annotations.collect());
+    user.metadata.owner_references = cluster_user.controller_owner_ref(&()).map(|o| vec![o]);
+    Ok(user)
+}
+
+async fn apply_namespaced_user(
+    cluster_user: &ClusterElasticsearchUser,
+    users: &Api<ElasticsearchUser>,
+) -> Result<ClusterElasticsearchUserStatus, OperatorError> {
+    let user = namespaced_user(cluster_user)?;
+    let params = PatchParams::apply(field_manager()).force();
+    let user = users
+        .patch(&user.name_any(), &params, &Patch::Apply(&user))
+        .await?;
+    let status = user.status.clone().unwrap_or_default();
+    Ok(ClusterElasticsearchUserStatus {
+        ok: status.ok,
+        error_message: status.error_message,
+        username: status.username,
+        elasticsearch_user: Some(format!(
+            "{}/{}",
+            user.namespace().unwrap_or_default(),
+            user.name_any()
+        )),
+    })
+}
+
+async fn reconcile(
+    cluster_user: Arc<ClusterElasticsearchUser>,
+    client: Arc<Client>,
+) -> Result<Action, OperatorError> {
+    debug!(
+        "Reconcile ClusterElasticsearchUser {}",
+        cluster_user.name_any()
+    );
+    let users: Api<ElasticsearchUser> = Api::default_namespaced((*client).clone());
+    let cluster_users: Api<ClusterElasticsearchUser> = Api::all((*client).clone());
+    let result = apply_namespaced_user(&cluster_user, &users).await;
+    let status = match &result {
+        Ok(status) => status.clone(),
+        Err(e) => ClusterElasticsearchUserStatus {
+            ok: false,
+            error_message: Some(e.to_string()),
+            ..cluster_user.status.clone().unwrap_or_default()
+        },
+    };
+    if cluster_user.status.as_ref() != Some(&status) {
+        let patch = Patch::Merge(serde_json::json!({ "status": status }));
+        cluster_users
+            .patch_status(&cluster_user.name_any(), &PatchParams::default(), &patch)
+            .await?;
+    }
+    result.map(|_| Action::requeue(Duration::from_secs(REQUEUE_SECONDS)))
+}
+
+fn error_policy(
+    cluster_user: Arc<ClusterElasticsearchUser>,
+    error: &OperatorError,
+    _client: Arc<Client>,
+) -> Action {
+    warn!(
+        "Reconcile ClusterElasticsearchUser {} failed: {}",
+        cluster_user.name_any(),
+        error
+    );
+    Action::requeue(
+        error
+            .retry_after()
+            .unwrap_or(Duration::from_secs(REQUEUE_SECONDS)),
+    )
+}
+
+/// Reconcile the ClusterElasticsearchUsers into ElasticsearchUsers of
+/// this namespace, see CLUSTER_USERS. Status changes of those are
+/// mirrored back.
+pub async fn run(client: Client, label_selector: Option<String>) {
+    let cluster_users: Api<ClusterElasticsearchUser> = Api::all(client.clone());
+    let users: Api<ElasticsearchUser> = Api::default_namespaced(client.clone());
+    let config = match &label_selector {
+        Some(selector) => watcher::Config::default().labels(selector),
+        None => watcher::Config::default(),
+    };
+    Controller::new(cluster_users, config)
+        .owns(users, watcher::Config::default().labels(CLUSTER_USER_LABEL))
+        .run(reconcile, error_policy, Arc::new(client))
+        .for_each(|res| async move {
+            if let Err(e) = res {
+                debug!("Reconcile ClusterElasticsearchUser failed: {:?}", e);
+            }
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::CustomResourceExt;
+
+    use super::*;
+
+    fn cluster_user(spec: serde_json::Value) -> ClusterElasticsearchUser {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "eeops.io/v1",
+            "kind": "ClusterElasticsearchUser",
+            "metadata": {"name": "ingest", "uid": "uid-c", "labels": {"tier": "prod"}},
+            "spec": spec,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn namespaced_user_owned_with_secret_namespace() {
+        let spec = serde_json::json!({
+            "secretRef": "ingest-es", "username": "ingest", "prefixes": ["logs-"],
+            "permissions": "Write", "secretNamespace": "logging",
+        });
+        let user = namespaced_user(&cluster_user(spec)).unwrap();
+        assert_eq!(user.name_any(), "cluster-ingest");
+        assert_eq!(user.spec.secret_namespace.as_deref(), Some("logging"));
+        assert_eq!(user.spec.username, "ingest");
+        assert_eq!(user.labels()[CLUSTER_USER_LABEL], "ingest");
+        assert_eq!(user.labels()["tier"], "prod");
+        assert_eq!(user.owner_references()[0].uid, "uid-c");
+
+        let spec = serde_json::json!({
+            "secretRef": "ingest-es", "username": "ingest", "prefixes": ["logs-"],
+            "permissions": "Write",
+        });
+        assert!(namespaced_user(&cluster_user(spec)).is_err());
+
+        // Flattened into a structural schema
+        let crd = serde_json::to_value(ClusterElasticsearchUser::crd()).unwrap();
+        let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+        assert!(spec["properties"]["secretNamespace"].is_object());
+    }
+}
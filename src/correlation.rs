@@ -0,0 +1,22 @@
+use std::future::Future;
+
+tokio::task_local! {
+    /// ID of the reconcile running in the current task.
+    static CORRELATION_ID: String;
+}
+
+/// Random ID, e.g. 3f9a0c1d2b4e5f60.
+pub fn new_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Run the future with the correlation ID, which is then part of its log
+/// lines and sent as X-Opaque-Id with its requests to Elasticsearch.
+pub async fn scope<F: Future>(id: String, f: F) -> F::Output {
+    CORRELATION_ID.scope(id, f).await
+}
+
+/// Correlation ID of the current task, None outside of a reconcile.
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(Clone::clone).ok()
+}
@@ -0,0 +1,64 @@
+use std::{str::from_utf8, sync::Arc};
+
+use futures_util::StreamExt;
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    runtime::{watcher, WatchStreamExt},
+    Api,
+};
+use log::{error, info, warn};
+
+use crate::Context;
+
+fn read_key<'a>(secret: &'a Secret, key: &str) -> Option<&'a str> {
+    secret
+        .data
+        .as_ref()?
+        .get(key)
+        .and_then(|b| from_utf8(&b.0).ok())
+}
+
+/// Watch the secret holding the operator's Elasticsearch credentials
+/// and switch to the new login once it changes and is working.
+pub async fn watch_credentials(secret_name: String, context: Arc<Context>) {
+    let api: Api<Secret> = Api::default_namespaced(context.client.clone());
+    let config = watcher::Config::default().fields(&format!("metadata.name={}", secret_name));
+    let settings = context.settings();
+    let mut current = (settings.env.username.clone(), settings.env.password.clone());
+    info!("Watching secret {} for credential changes.", secret_name);
+    let mut secrets = watcher(api, config).applied_objects().boxed();
+    while let Some(event) = secrets.next().await {
+        let secret = match event {
+            Ok(secret) => secret,
+            Err(e) => {
+                warn!("Error watching credentials secret {}: {}", secret_name, e);
+                continue;
+            }
+        };
+        let (Some(username), Some(password)) = (
+            read_key(&secret, "ELASTIC_USERNAME"),
+            read_key(&secret, "ELASTIC_PASSWORD"),
+        ) else {
+            warn!(
+                "Credentials secret {} lacks ELASTIC_USERNAME or ELASTIC_PASSWORD.",
+                secret_name
+            );
+            continue;
+        };
+        if (username, password) == (current.0.as_str(), current.1.expose()) {
+            continue;
+        }
+        let candidate = context.elastic.clone_with_new_login(username, password);
+        match candidate.connection_ok().await {
+            Ok(()) => {
+                context.elastic.set_login(username, password);
+                current = (username.to_string(), password.into());
+                info!("Reloaded Elasticsearch credentials from {}.", secret_name);
+            }
+            Err(e) => error!(
+                "New credentials in {} are not working, keep the old ones: {}",
+                secret_name, e
+            ),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::{fmt::Write, sync::Arc};
+
+use kube::ResourceExt;
+
+use crate::ElasticsearchUser;
+
+/// Seconds until the browser reloads the page.
+const REFRESH_SECONDS: u32 = 30;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse}\
+th,td{border-bottom:1px solid #ccc;padding:.3em .8em;text-align:left;vertical-align:top}\
+.failing{color:#b00}.ok{color:#070}";
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Read-only HTML page listing the managed ElasticsearchUsers,
+/// failing ones first.
+pub fn render(users: &[Arc<ElasticsearchUser>]) -> String {
+    let mut users: Vec<&ElasticsearchUser> = users.iter().map(AsRef::as_ref).collect();
+    let ok = |user: &ElasticsearchUser| user.status.as_ref().is_some_and(|s| s.ok);
+    users.sort_by_key(|user| (ok(user), user.namespace(), user.name_any()));
+    let failing = users.iter().filter(|user| !ok(user)).count();
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+        <meta http-equiv=\"refresh\" content=\"{}\">\
+        <title>ext-elasticsearch-operator</title><style>{}</style></head><body>\n\
+        <h1>ElasticsearchUsers</h1>\n<p>{} managed, {} not ready</p>\n<table>\n\
+        <tr><th>ElasticsearchUser</th><th>Username</th><th>Secret</th><th>Status</th>\
+        <th>Last sync</th><th>Last successful sync</th><th>Last error</th></tr>\n",
+        REFRESH_SECONDS,
+        STYLE,
+        users.len(),
+        failing
+    );
+    for user in users {
+        let status = user.status.as_ref();
+        let (class, state) = match status {
+            Some(s) if s.ok => ("ok", "Ready"),
+            Some(_) => ("failing", "Failing"),
+            None => ("failing", "Pending"),
+        };
+        let error = status
+            .and_then(|s| s.error_message.as_deref())
+            .map(|message| match status.and_then(|s| s.error_code) {
+                Some(code) => format!("{}: {}", code.as_str(), message),
+                None => message.to_string(),
+            })
+            .unwrap_or_default();
+        let _ = writeln!(
+            html,
+            "<tr><td>{}/{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td>\
+            <td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&user.namespace().unwrap_or_default()),
+            escape(&user.name_any()),
+            escape(user.username()),
+            escape(&user.spec.secret_ref),
+            class,
+            state,
+            escape(
+                status
+                    .and_then(|s| s.last_sync_time.as_deref())
+                    .unwrap_or("")
+            ),
+            escape(
+                status
+                    .and_then(|s| s.last_successful_sync.as_deref())
+                    .unwrap_or("")
+            ),
+            escape(&error),
+        );
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn user(name: &str, status: serde_json::Value) -> Arc<ElasticsearchUser> {
+        Arc::new(fixtures::user(
+            name,
+            serde_json::json!({ "status": status }),
+        ))
+    }
+
+    #[test]
+    fn failing_first_and_escaped() {
+        let users = vec![
+            user("a", serde_json::json!({"ok": true})),
+            user(
+                "b",
+                serde_json::json!({
+                    "ok": false, "errorCode": "InvalidSpec", "errorMessage": "<script>"
+                }),
+            ),
+        ];
+        let html = render(&users);
+        assert!(html.contains("2 managed, 1 not ready"));
+        assert!(html.contains("InvalidSpec: &lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(html.find("default/b").unwrap() < html.find("default/a").unwrap());
+    }
+}
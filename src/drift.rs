@@ -0,0 +1,369 @@
+use std::{collections::BTreeSet, str::from_utf8};
+
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    api::{ListParams, PartialObjectMeta},
+    Client, ResourceExt,
+};
+
+use crate::{
+    class::{apply_class, get_class},
+    elasticsearch::{ElasticAdmin, ElasticError, Role, ROLE_REFERENCES_KEY},
+    env::Env,
+    error::OperatorError,
+    reconciliation::{
+        current_secret_name, desired_role, role_reference, secret_api, user_role_name, ROLE_PREFIX,
+    },
+    ElasticsearchUser, IdentityMode, OWNED_BY_ANNOTATION,
+};
+
+enum State {
+    InSync,
+    Drifted(Vec<String>),
+    Orphaned(String),
+}
+
+struct Row {
+    resource: String,
+    username: String,
+    state: State,
+}
+
+fn secret_value<'a>(secret: &'a Secret, key: &str) -> Option<&'a str> {
+    secret
+        .data
+        .as_ref()?
+        .get(key)
+        .and_then(|b| from_utf8(&b.0).ok())
+}
+
+/// Differences between the CR and the actual secret, role and user.
+async fn drift_of(
+    user: &ElasticsearchUser,
+    client: &Client,
+    elastic: &ElasticAdmin,
+) -> Result<Vec<String>, OperatorError> {
+    let class = get_class(user, client).await?;
+    let user = &match apply_class(user, class.as_ref()) {
+        Ok(user) => user,
+        Err(e) => return Ok(vec![e.to_string()]),
+    };
+    let mut drift = Vec::new();
+    let username = user.username();
+    let role_name = user_role_name(user);
+    let format = user.spec.secret_format();
+
+    match elastic.get_role(&role_name).await? {
+        None => drift.push(format!("role {} missing", role_name)),
+        Some(role) if !role.same_privileges(&desired_role(user)) => {
+            drift.push(format!("role {} differs: {}", role_name, role))
+        }
+        Some(_) => (),
+    }
+
+    if user.spec.identity_mode == IdentityMode::RoleOnly {
+        return Ok(drift);
+    }
+
+    let secret_name = current_secret_name(user);
+    let secret = secret_api(user, client).get_opt(&secret_name).await?;
+    match &secret {
+        None => drift.push(format!("secret {} missing", secret_name)),
+        Some(secret) => {
+            if secret_value(secret, format.username_key()) != Some(username) {
+                drift.push("secret username differs".to_string());
+            }
+            for (key, value) in format.connection_values(&elastic.url) {
+                if secret_value(secret, &key) != Some(value.as_str()) {
+                    drift.push(format!("secret {} differs", key));
+                }
+            }
+        }
+    }
+
+    match elastic.get_user(username).await? {
+        None => drift.push("user missing".to_string()),
+        Some(es_user) => {
+            if !es_user.roles.contains(&role_name) {
+                drift.push(format!("user lacks role {}", role_name));
+            }
+            let password = secret
+                .as_ref()
+                .and_then(|s| secret_value(s, format.password_key()));
+            if let Some(password) = password {
+                let login = elastic.clone_with_new_login(username, password);
+                match login.get_self().await {
+                    Err(ElasticError::WrongCredentials) => {
+                        drift.push("password differs from secret".to_string())
+                    }
+                    Err(e) => Err(e)?,
+                    Ok(_) => (),
+                }
+            }
+        }
+    }
+    Ok(drift)
+}
+
+/// Roles created by the operator for CRs of the reconciled namespaces,
+/// of which none exists anymore.
+async fn orphaned_roles(
+    enabled: &impl Fn(&str) -> bool,
+    known_references: &BTreeSet<String>,
+    elastic: &ElasticAdmin,
+) -> Result<Vec<Row>, OperatorError> {
+    let mut rows = Vec::new();
+    for (name, raw) in elastic.list_roles().await? {
+        if raw["metadata"][ROLE_REFERENCES_KEY].is_null() {
+            continue;
+        }
+        let Ok(role) = serde_json::from_value::<Role>(raw) else {
+            continue;
+        };
+        let references = role.references();
+        // Roles shared with other namespaces are not ours to judge
+        if references.iter().all(|r| {
+            r.split_once('/')
+                .is_some_and(|(namespace, _)| enabled(namespace))
+        }) && references.is_disjoint(known_references)
+        {
+            rows.push(Row {
+                resource: references.into_iter().collect::<Vec<_>>().join(", "),
+                username: name.strip_prefix(ROLE_PREFIX).unwrap_or(&name).to_string(),
+                state: State::Orphaned(format!("role {} without ElasticsearchUser", name)),
+            });
+        }
+    }
+    Ok(rows)
+}
+
+/// Users created by the operator for CRs of the reconciled namespaces,
+/// of which none exists anymore, e.g. kept on delete.
+async fn orphaned_users(
+    enabled: &impl Fn(&str) -> bool,
+    known_references: &BTreeSet<String>,
+    elastic: &ElasticAdmin,
+) -> Result<Vec<Row>, OperatorError> {
+    let mut rows = Vec::new();
+    for (username, user) in elastic
+        .list_operator_users("eeops_namespace", ROLE_PREFIX)
+        .await?
+    {
+        let Some(metadata) = user.metadata else {
+            continue;
+        };
+        let Some(namespace) = metadata["eeops_namespace"].as_str().filter(|n| enabled(n)) else {
+            continue;
+        };
+        let name = metadata
+            .get("eeops_name")
+            .and_then(|n| n.as_str())
+            .unwrap_or_default();
+        let reference = format!("{}/{}", namespace, name);
+        if !known_references.contains(&reference) {
+            let disabled = if user.enabled == Some(false) {
+                " (disabled)"
+            } else {
+                ""
+            };
+            rows.push(Row {
+                resource: reference,
+                state: State::Orphaned(format!(
+                    "user {} without ElasticsearchUser{}",
+                    username, disabled
+                )),
+                username,
+            });
+        }
+    }
+    Ok(rows)
+}
+
+/// Secrets of the reconciled namespaces owned by an ElasticsearchUser which
+/// doesn't exist anymore. Secrets marked by eeops.io/owned-by may belong to
+/// an ElasticsearchUser of any namespace, they are only reported if this
+/// replica reconciles all namespaces.
+fn orphaned_secrets(
+    secrets: Vec<PartialObjectMeta<Secret>>,
+    all_namespaces: bool,
+    known_uids: &BTreeSet<String>,
+) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for secret in secrets {
+        let owner = secret
+            .owner_references()
+            .iter()
+            .find(|o| o.kind == "ElasticsearchUser")
+            .map(|o| (o.uid.clone(), o.name.clone()))
+            .or_else(|| {
+                let uid = secret.annotations().get(OWNED_BY_ANNOTATION)?;
+                all_namespaces.then(|| (uid.clone(), format!("uid {}", uid)))
+            });
+        let Some((uid, owner)) = owner else {
+            continue;
+        };
+        if !known_uids.contains(&uid) {
+            rows.push(Row {
+                resource: format!(
+                    "{}/{}",
+                    secret.namespace().unwrap_or_default(),
+                    secret.name_any()
+                ),
+                username: String::new(),
+                state: State::Orphaned(format!("secret without ElasticsearchUser {}", owner)),
+            });
+        }
+    }
+    rows
+}
+
+/// Compare all CRs of the reconciled namespaces with Elasticsearch and
+/// print a table of in-sync, drifted and orphaned entries. With a label
+/// selector, only the matching CRs are compared, and orphans are not
+/// reported, as they may belong to CRs not matching.
+pub async fn print_drift_report(
+    client: &Client,
+    elastic: &ElasticAdmin,
+    env: &Env,
+) -> Result<(), OperatorError> {
+    let own_namespace = client.default_namespace();
+    let (users, config) = crate::watched_api::<ElasticsearchUser>(client, env, own_namespace);
+    let enabled = |namespace: &str| env.namespaces.enabled(namespace, own_namespace);
+    let label_selector = env.watch_label_selector.as_deref();
+
+    let mut rows = Vec::new();
+    let mut known_references = BTreeSet::new();
+    let mut known_uids = BTreeSet::new();
+    let mut params = ListParams::default();
+    if let Some(selector) = label_selector {
+        params = params.labels(selector);
+    }
+    if let Some(fields) = &config.field_selector {
+        params = params.fields(fields);
+    }
+    for user in users.list(&params).await? {
+        let namespace = user.namespace().unwrap_or_default();
+        if !enabled(&namespace) {
+            continue;
+        }
+        known_references.insert(role_reference(&user));
+        known_uids.extend(user.uid());
+        let drift = drift_of(&user, client, elastic).await?;
+        rows.push(Row {
+            resource: format!("{}/{}", namespace, user.name_any()),
+            username: user.username().to_string(),
+            state: if drift.is_empty() {
+                State::InSync
+            } else {
+                State::Drifted(drift)
+            },
+        });
+    }
+    if label_selector.is_none() {
+        rows.extend(orphaned_users(&enabled, &known_references, elastic).await?);
+        rows.extend(orphaned_roles(&enabled, &known_references, elastic).await?);
+        let (secrets, config) = crate::watched_api::<Secret>(client, env, own_namespace);
+        let mut params = ListParams::default();
+        if let Some(fields) = &config.field_selector {
+            params = params.fields(fields);
+        }
+        let secrets = secrets
+            .list_metadata(&params)
+            .await?
+            .items
+            .into_iter()
+            .filter(|s| enabled(&s.namespace().unwrap_or_default()))
+            .collect();
+        rows.extend(orphaned_secrets(secrets, env.namespaces.all(), &known_uids));
+    }
+
+    let resource_width = rows
+        .iter()
+        .map(|r| r.resource.len())
+        .max()
+        .unwrap_or(0)
+        .max("RESOURCE".len());
+    let username_width = rows
+        .iter()
+        .map(|r| r.username.len())
+        .max()
+        .unwrap_or(0)
+        .max("USERNAME".len());
+    println!(
+        "{:<9} {:<rw$} {:<uw$} DETAILS",
+        "STATE",
+        "RESOURCE",
+        "USERNAME",
+        rw = resource_width,
+        uw = username_width
+    );
+    for row in rows {
+        let (state, details) = match row.state {
+            State::InSync => ("in-sync", String::new()),
+            State::Drifted(drift) => ("drifted", drift.join("; ")),
+            State::Orphaned(details) => ("orphaned", details),
+        };
+        println!(
+            "{:<9} {:<rw$} {:<uw$} {}",
+            state,
+            row.resource,
+            row.username,
+            details,
+            rw = resource_width,
+            uw = username_width
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+    use kube::api::ObjectMeta;
+
+    use super::*;
+
+    fn secret(name: &str, owner_uid: &str, annotated: bool) -> PartialObjectMeta<Secret> {
+        let mut metadata = ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some("default".to_string()),
+            ..Default::default()
+        };
+        if annotated {
+            metadata.annotations =
+                Some([(OWNED_BY_ANNOTATION.to_string(), owner_uid.to_string())].into());
+        } else {
+            metadata.owner_references = Some(vec![OwnerReference {
+                kind: "ElasticsearchUser".to_string(),
+                name: name.to_string(),
+                uid: owner_uid.to_string(),
+                ..Default::default()
+            }]);
+        }
+        PartialObjectMeta {
+            types: None,
+            metadata,
+            _phantom: Default::default(),
+        }
+    }
+
+    #[test]
+    fn secrets_of_deleted_users_orphaned() {
+        let known = BTreeSet::from(["uid-a".to_string()]);
+        let secrets = || {
+            vec![
+                secret("a", "uid-a", false),
+                secret("b", "uid-b", false),
+                secret("c", "uid-c", true),
+            ]
+        };
+        let orphaned = |all| -> Vec<String> {
+            orphaned_secrets(secrets(), all, &known)
+                .into_iter()
+                .map(|r| r.resource)
+                .collect()
+        };
+        assert_eq!(orphaned(false), ["default/b"]);
+        assert_eq!(orphaned(true), ["default/b", "default/c"]);
+    }
+}
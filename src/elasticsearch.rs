@@ -1,87 +1,418 @@
+mod api;
+mod api_key;
+mod compression;
 mod error;
+#[cfg(test)]
+pub mod mock;
 mod role;
+mod sensitive;
+mod trace;
 mod user;
-use std::{collections::HashMap, fmt::Display, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        RwLock,
+    },
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use log::trace;
+use log::{trace, warn};
 use reqwest::{
     header::{self, HeaderMap, HeaderValue},
-    Client,
+    Certificate, Client, Response, Url,
 };
+use serde::Serialize;
+use serde_json::{json, Value};
 
+pub use api::ElasticsearchApi;
+pub use api_key::ApiKey;
+pub use compression::set_compression;
 pub use error::ElasticError;
-pub use role::{IndexPermission, Privileges, Role};
+pub use role::{
+    GlobalApplicationPrivileges, GlobalApplications, GlobalPrivileges, GlobalProfilePrivileges,
+    IndexPermission, Privileges, Role, ROLE_REFERENCES_KEY,
+};
+pub use sensitive::{excerpt, redact, Sensitive};
+pub use trace::set_trace_http;
+use trace::SendTraced;
 pub use user::User;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Results per request of the paginated query APIs.
+const QUERY_PAGE_SIZE: usize = 500;
+
+/// Default of the connect and request timeouts.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Default of ConnectionConfig::reconnect_after.
+pub const DEFAULT_RECONNECT_AFTER: u32 = 3;
+
+/// TLS and connection pool settings of the connection to Elasticsearch.
+#[derive(Clone)]
+pub struct ConnectionConfig {
+    pub skip_verify: bool,
+    /// Additionally trusted CA, e.g. of a self-signed cluster.
+    pub ca_certificate: Option<Certificate>,
+    /// Idle connections are closed after it, before e.g.
+    /// a load balancer drops them silently. Default 90s.
+    pub pool_idle_timeout: Option<Duration>,
+    /// Unlimited if unset, 0 opens a new connection per request.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// TCP keep-alive probes, keeping idle connections open.
+    pub tcp_keepalive: Option<Duration>,
+    /// HTTP/2 without negotiation, e.g. via a proxy speaking it.
+    pub http2: bool,
+    /// Establishing the TCP and TLS connection.
+    pub connect_timeout: Duration,
+    /// Whole request, including the response body.
+    pub request_timeout: Duration,
+    /// Connection errors in a row after which the connection pool is
+    /// dropped and the host resolved again, e.g. after Elasticsearch
+    /// moved to a new address. 0 disables.
+    pub reconnect_after: u32,
+    /// Sent as X-Eeops-Instance and in the User-Agent, to tell apart
+    /// changes of installations sharing a cluster in its logs.
+    pub instance_id: Option<String>,
+    /// "namespace/name" of the operator pod, in the User-Agent.
+    pub pod: Option<String>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            skip_verify: false,
+            ca_certificate: None,
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            tcp_keepalive: None,
+            http2: false,
+            connect_timeout: DEFAULT_TIMEOUT,
+            request_timeout: DEFAULT_TIMEOUT,
+            reconnect_after: DEFAULT_RECONNECT_AFTER,
+            instance_id: None,
+            pod: None,
+        }
+    }
+}
 
 pub struct ElasticAdmin {
+    /// Base URL without trailing slash, see base_url.
     pub url: String,
-    client: Client,
-    skip_verify: bool,
+    base: Url,
+    /// Swapped when the login changes, see set_login,
+    /// or on repeated connection errors, see reconnect.
+    client: RwLock<Client>,
+    /// To rebuild the client with the current login.
+    login: RwLock<(String, Sensitive)>,
+    connection: ConnectionConfig,
+    connection_failures: AtomicU32,
+    /// Elasticsearch Serverless project, detected by connection_ok.
+    serverless: AtomicBool,
 }
 
-fn username_password_to_basic(username: impl Display, password: impl Display) -> String {
-    let basic_auth_b64 = STANDARD.encode(format!("{}:{}", username, password));
+fn username_password_to_basic(username: impl Display, password: &Sensitive) -> String {
+    let basic_auth_b64 = STANDARD.encode(format!("{}:{}", username, password.expose()));
     format!("Basic {}", basic_auth_b64)
 }
 
+/// Parse and normalize ELASTIC_URL, which may include a path prefix,
+/// e.g. "https://gateway.example.com/es/" to "https://gateway.example.com/es".
+pub fn base_url(url: &str) -> Result<Url, String> {
+    let mut base = Url::parse(url.trim()).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    if !matches!(base.scheme(), "http" | "https") || !base.has_host() {
+        return Err(format!("URL {} must be http(s)://host[:port][/path].", url));
+    }
+    if base.query().is_some() || base.fragment().is_some() {
+        return Err(format!("URL {} must not have a query or fragment.", url));
+    }
+    if !base.username().is_empty() || base.password().is_some() {
+        return Err(format!(
+            "URL {} must not contain credentials, use ELASTIC_USERNAME and ELASTIC_PASSWORD.",
+            base.host_str().unwrap_or(url)
+        ));
+    }
+    let path = base.path().trim_end_matches('/').to_string();
+    base.set_path(&path);
+    Ok(base)
+}
+
+/// e.g. "ext-elasticsearch-operator/0.5.0 (instance=prod; pod=eeops/eeops-7d9f)"
+fn user_agent(connection: &ConnectionConfig) -> String {
+    let details: Vec<String> = [
+        connection
+            .instance_id
+            .as_ref()
+            .map(|i| format!("instance={}", i)),
+        connection.pod.as_ref().map(|p| format!("pod={}", p)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    match details.is_empty() {
+        true => format!("ext-elasticsearch-operator/{}", VERSION),
+        false => format!(
+            "ext-elasticsearch-operator/{} ({})",
+            VERSION,
+            details.join("; ")
+        ),
+    }
+}
+
+fn build_client(
+    username: impl Display,
+    password: &Sensitive,
+    connection: &ConnectionConfig,
+) -> Client {
+    let mut default_header_map = HeaderMap::new();
+    default_header_map.insert(
+        "Content-Type",
+        HeaderValue::from_str("Application/Json").unwrap(),
+    );
+    let mut auth_value =
+        HeaderValue::from_str(&username_password_to_basic(username, password)).unwrap();
+    auth_value.set_sensitive(true);
+    default_header_map.insert(header::AUTHORIZATION, auth_value);
+    // Validated when the environment is loaded
+    if let Some(Ok(instance)) = connection.instance_id.as_deref().map(HeaderValue::from_str) {
+        default_header_map.insert("X-Eeops-Instance", instance);
+    }
+    let mut builder = Client::builder()
+        .connect_timeout(connection.connect_timeout)
+        .timeout(connection.request_timeout)
+        .danger_accept_invalid_certs(connection.skip_verify)
+        .default_headers(default_header_map)
+        .user_agent(user_agent(connection))
+        .gzip(compression::enabled())
+        .tcp_keepalive(connection.tcp_keepalive);
+    if let Some(ca) = &connection.ca_certificate {
+        builder = builder.add_root_certificate(ca.clone());
+    }
+    if let Some(timeout) = connection.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(timeout);
+    }
+    if let Some(max) = connection.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if connection.http2 {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder
+        .build()
+        .expect("Unexpected error in building HTTP Client")
+}
+
+/// Redacted body of a failed response, see excerpt
+/// to embed it into errors.
+async fn error_body(res: Response) -> reqwest::Result<String> {
+    Ok(redact(&res.text().await?))
+}
+
+/// Whether the cluster does not know the endpoint, e.g. the
+/// query APIs of the security index on older versions.
+fn endpoint_missing(status: u16, body: &str) -> bool {
+    matches!(status, 404 | 405) || status == 400 && body.contains("no handler found")
+}
+
+/// Typed error of a failed response, see ElasticError::from_response.
+async fn response_error(res: Response, context: impl AsRef<str>) -> reqwest::Result<ElasticError> {
+    let status = res.status().as_u16();
+    let body = error_body(res).await?;
+    Ok(ElasticError::from_response(status, &body, context))
+}
+
 impl ElasticAdmin {
     pub fn new(
         url: &str,
         username: impl ToString,
-        password: impl ToString,
-        skip_verify: bool,
+        password: impl Into<Sensitive>,
+        connection: ConnectionConfig,
     ) -> Self {
-        let url = url.trim_end_matches('/');
-        let mut default_header_map = HeaderMap::new();
-        default_header_map.insert(
-            "Content-Type",
-            HeaderValue::from_str("Application/Json").unwrap(),
-        );
-        let mut auth_value = HeaderValue::from_str(&username_password_to_basic(
-            username.to_string(),
-            password.to_string(),
-        ))
-        .unwrap();
-        auth_value.set_sensitive(true);
-        default_header_map.insert(header::AUTHORIZATION, auth_value);
+        let base = base_url(url).expect("Elasticsearch URL is validated on load");
+        let username = username.to_string();
+        let password = password.into();
         Self {
-            url: url.to_string(),
-            client: Client::builder()
-                .timeout(Duration::from_millis(5_000))
-                .danger_accept_invalid_certs(skip_verify)
-                .default_headers(default_header_map)
-                .user_agent(format!("ext-elasticsearch-operator/{}", VERSION))
-                .build()
-                .expect("Unexpected error in building HTTP Client"),
-            skip_verify,
-        }
-    }
-    pub fn clone_with_new_login(&self, username: impl Display, password: impl Display) -> Self {
+            url: base.as_str().trim_end_matches('/').to_string(),
+            base,
+            client: RwLock::new(build_client(&username, &password, &connection)),
+            login: RwLock::new((username, password)),
+            connection,
+            connection_failures: AtomicU32::new(0),
+            serverless: AtomicBool::new(false),
+        }
+    }
+    fn client(&self) -> Client {
+        // Client is reference counted, cloning is cheap
+        self.client.read().expect("Client lock poisoned").clone()
+    }
+    /// Use a new login for all following requests,
+    /// e.g. after the admin password got rotated.
+    pub fn set_login(&self, username: impl Display, password: impl Into<Sensitive>) {
+        let login = (username.to_string(), password.into());
+        *self.client.write().expect("Client lock poisoned") =
+            build_client(&login.0, &login.1, &self.connection);
+        *self.login.write().expect("Login lock poisoned") = login;
+    }
+    /// Replace the client with a new one, whose connection pool is
+    /// empty, so the host is resolved again on the next request.
+    fn reconnect(&self) {
+        let login = self.login.read().expect("Login lock poisoned");
+        *self.client.write().expect("Client lock poisoned") =
+            build_client(&login.0, &login.1, &self.connection);
+    }
+    /// Count connection errors in a row, see ConnectionConfig::reconnect_after.
+    fn record_connection<T>(&self, result: &reqwest::Result<T>) {
+        let failed = match result {
+            Ok(_) => false,
+            Err(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+        };
+        if !failed {
+            self.connection_failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        let limit = self.connection.reconnect_after;
+        let failures = self.connection_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        // Only the request reaching the limit reconnects
+        if limit > 0 && failures == limit {
+            warn!(
+                "{} connection errors in a row, reconnect to Elasticsearch at {}.",
+                failures, self.url
+            );
+            self.reconnect();
+            self.connection_failures.store(0, Ordering::Relaxed);
+        }
+    }
+    pub fn clone_with_new_login(
+        &self,
+        username: impl ToString,
+        password: impl Into<Sensitive>,
+    ) -> Self {
         // TODO reuse Client?
-        Self::new(&self.url, username, password, self.skip_verify)
+        Self::new(&self.url, username, password, self.connection.clone())
     }
-    fn format_url(&self, uri: impl std::fmt::Display) -> String {
-        format!("{}{}", self.url, uri)
+    /// Append e.g. "/_security/user/app?refresh=true" to the base URL,
+    /// keeping its path prefix and encoding each path segment.
+    fn format_url(&self, uri: impl std::fmt::Display) -> Url {
+        let uri = uri.to_string();
+        let (path, query) = match uri.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (uri.as_str(), None),
+        };
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("Elasticsearch URL has a host")
+            .pop_if_empty()
+            .extend(path.trim_start_matches('/').split('/'));
+        url.set_query(query);
+        url
     }
     pub async fn get_self(&self) -> Result<User, ElasticError> {
         let res = self
-            .client
+            .client()
             .get(self.format_url("/_security/_authenticate"))
-            .send()
+            .send_traced(self)
             .await?;
 
         if res.status().as_u16() == 401 {
             return Err(ElasticError::WrongCredentials);
         }
-        Ok(res.json().await.expect("Self not serializable"))
+        // e.g. 503 while the cluster is starting up
+        let res = res.error_for_status()?;
+        Ok(res.json().await?)
+    }
+    /// Version number of the connected cluster, e.g. 8.12.1
+    pub async fn version(&self) -> Result<String> {
+        let res = self
+            .client()
+            .get(self.format_url("/"))
+            .send_traced(self)
+            .await?
+            .error_for_status()?;
+        let body: serde_json::Value = res.json().await?;
+        body["version"]["number"]
+            .as_str()
+            .map(ToString::to_string)
+            .ok_or(ElasticError::Custom("Response did not contain a version".to_string()).into())
+    }
+    /// Health status of the cluster: green, yellow or red.
+    /// None on Serverless, where cluster health isn't exposed.
+    pub async fn cluster_health(&self) -> Result<Option<String>> {
+        if self.is_serverless() {
+            return Ok(None);
+        }
+        let res = self
+            .client()
+            .get(self.format_url("/_cluster/health"))
+            .send_traced(self)
+            .await?
+            .error_for_status()?;
+        let body: serde_json::Value = res.json().await?;
+        Ok(body["status"].as_str().map(ToString::to_string))
+    }
+    /// Build flavor of the connected cluster, e.g. default or serverless.
+    pub async fn build_flavor(&self) -> Result<Option<String>> {
+        let res = self
+            .client()
+            .get(self.format_url("/"))
+            .send_traced(self)
+            .await?
+            .error_for_status()?;
+        let body: serde_json::Value = res.json().await?;
+        Ok(body["version"]["build_flavor"]
+            .as_str()
+            .map(ToString::to_string))
+    }
+    /// Reason the security features can't be used, e.g. disabled or
+    /// an expired license. None if available.
+    pub async fn security_unavailable(&self) -> Result<Option<String>> {
+        if self.is_serverless() {
+            // Security is always enabled, _xpack and _license don't exist
+            return Ok(None);
+        }
+        let xpack: serde_json::Value = self
+            .client()
+            .get(self.format_url("/_xpack?categories=features"))
+            .send_traced(self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let security = &xpack["features"]["security"];
+        if security["available"] == false {
+            return Ok(Some(
+                "security is not available with the license".to_string(),
+            ));
+        }
+        if security["enabled"] == false {
+            return Ok(Some(
+                "security is disabled (xpack.security.enabled)".to_string(),
+            ));
+        }
+        let license: serde_json::Value = self
+            .client()
+            .get(self.format_url("/_license"))
+            .send_traced(self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match license["license"]["status"].as_str() {
+            Some("active") | None => Ok(None),
+            Some(status) => Ok(Some(format!("license is {}", status))),
+        }
+    }
+    pub fn is_serverless(&self) -> bool {
+        self.serverless.load(Ordering::Relaxed)
     }
     pub async fn connection_ok(&self) -> Result<(), ElasticError> {
         let body = self.get_self().await?;
-        if !body.roles.contains(&"superuser".into()) {
+        let flavor = self.build_flavor().await.ok().flatten();
+        let serverless = flavor.as_deref() == Some("serverless");
+        self.serverless.store(serverless, Ordering::Relaxed);
+        // Serverless has no superuser, privileges are managed per project
+        if !serverless && !body.roles.contains(&"superuser".into()) {
             return Err(ElasticError::NotSuperuser);
         }
         Ok(())
@@ -92,104 +423,237 @@ impl ElasticAdmin {
     /// put or patch.
     pub async fn create_role(&self, name: impl Display, role: &Role) -> Result<()> {
         let res = self
-            .client
+            .client()
             .post(self.format_url(format!("/_security/role/{}", name)))
             .json(&role)
-            .send()
+            .send_traced(self)
             .await?;
         trace!("Status code creating role {}: {}", name, res.status());
+        if !res.status().is_success() {
+            return Err(response_error(res, format!("Error creating role {}", name))
+                .await?
+                .into());
+        }
         Ok(())
     }
     pub async fn delete_role(&self, name: impl Display) -> Result<bool> {
         let res = self
-            .client
+            .client()
             .delete(self.format_url(format!("/_security/role/{}", name)))
-            .send()
+            .send_traced(self)
             .await?;
         trace!("Status code of deleting role {}: {}", name, res.status());
         if res.status().as_u16() == 404 {
             return Ok(false);
         }
         if !res.status().is_success() {
-            return Err(ElasticError::Custom(format!(
-                "Error deleting role: {}",
-                res.text()
-                    .await
-                    .context("Failed to read body of failed delete role request.")?
-            ))
-            .into());
+            return Err(response_error(res, "Error deleting role").await?.into());
         }
         Ok(true)
     }
     pub async fn get_role(&self, name: impl Display) -> Result<Option<Role>> {
         let res = self
-            .client
+            .client()
             .get(self.format_url(format!("/_security/role/{}", name)))
-            .send()
+            .send_traced(self)
             .await?;
         if res.status().as_u16() == 404 {
             return Ok(None);
         }
         if !res.status().is_success() {
-            return Err(ElasticError::Custom(format!(
-                "Error getting role {}: {}",
-                name,
-                res.text().await?
-            ))
-            .into());
+            return Err(response_error(res, format!("Error getting role {}", name))
+                .await?
+                .into());
         }
         let body = res.text().await?;
-        let mut role_map: HashMap<String, Role> = serde_json::from_str(body.as_str()).context(
-            format!("Failed to parse role into role map format: {}", body),
-        )?;
-        let role = role_map
+        let mut role_map: HashMap<String, Role> =
+            serde_json::from_str(body.as_str()).context(format!(
+                "Failed to parse role into role map format: {}",
+                excerpt(&body)
+            ))?;
+        let mut role = role_map
             .remove(name.to_string().as_str())
             .ok_or(ElasticError::Custom(format!(
                 "Unexpected response: Got role {} \
                 successfully, but response did not contain role.",
                 name,
             )))?;
+        // Only returned, it can't be written back
+        role.unmanaged.remove("transient_metadata");
         Ok(Some(role))
     }
+    /// Pages through a query API of the security index, e.g. for users,
+    /// sorted by `sort_field`. None if the cluster does not support it.
+    async fn query_all(
+        &self,
+        path: &str,
+        results_key: &str,
+        sort_field: &str,
+        query: Option<&Value>,
+    ) -> Result<Option<Vec<Value>>> {
+        let mut results = Vec::new();
+        let mut search_after = None;
+        loop {
+            let mut body = json!({"size": QUERY_PAGE_SIZE, "sort": [sort_field]});
+            if let Some(query) = query {
+                body["query"] = query.clone();
+            }
+            if let Some(after) = search_after.take() {
+                body["search_after"] = after;
+            }
+            let res = self
+                .client()
+                .post(self.format_url(path))
+                .json(&body)
+                .send_traced(self)
+                .await?;
+            if !res.status().is_success() {
+                let status = res.status().as_u16();
+                let text = error_body(res).await?;
+                if endpoint_missing(status, &text) {
+                    return Ok(None);
+                }
+                let context = format!("Error querying {}", path);
+                return Err(ElasticError::from_response(status, &text, context).into());
+            }
+            let mut page: Value = res.json().await?;
+            let items = match page[results_key].take() {
+                Value::Array(items) => items,
+                _ => Vec::new(),
+            };
+            let full_page = items.len() == QUERY_PAGE_SIZE;
+            search_after = items
+                .last()
+                .map(|item| item["_sort"].clone())
+                .filter(|after| !after.is_null());
+            results.extend(items);
+            if !full_page || search_after.is_none() {
+                return Ok(Some(results));
+            }
+        }
+    }
+    /// All entries of a GET API returning them by name, e.g. /_security/user,
+    /// without the built-in ones.
+    async fn get_all(&self, path: &str) -> Result<BTreeMap<String, Value>> {
+        let res = self
+            .client()
+            .get(self.format_url(path))
+            .send_traced(self)
+            .await?;
+        if !res.status().is_success() {
+            let context = format!("Error getting {}", path);
+            return Err(response_error(res, context).await?.into());
+        }
+        let mut all: BTreeMap<String, Value> = res.json().await?;
+        all.retain(|_, value| value["metadata"]["_reserved"] != true);
+        Ok(all)
+    }
+    /// Native users by username, without the built-in ones, narrowed by
+    /// the query where the query API is supported (8.14+). Older clusters
+    /// return all at once.
+    async fn query_users(&self, query: Option<&Value>) -> Result<BTreeMap<String, User>> {
+        let users = match self
+            .query_all("/_security/_query/user", "users", "username", query)
+            .await?
+        {
+            Some(users) => users
+                .into_iter()
+                .filter_map(|user| Some((user["username"].as_str()?.to_string(), user)))
+                .filter(|(_, user)| user["metadata"]["_reserved"] != true)
+                .collect(),
+            None => self.get_all("/_security/user").await?,
+        };
+        users
+            .into_iter()
+            .map(|(name, user)| {
+                let user = serde_json::from_value(user)
+                    .with_context(|| format!("Failed to parse user {}", name))?;
+                Ok((name, user))
+            })
+            .collect()
+    }
+    /// All native users by username, without the built-in ones.
+    pub async fn list_users(&self) -> Result<BTreeMap<String, User>> {
+        self.query_users(None).await
+    }
+    /// Users created by the operator, by the metadata key `metadata_key`.
+    /// User metadata can't be queried, so the query narrows down to users
+    /// with a role named like the generated ones, e.g. role-app, instead
+    /// of downloading the whole user registry.
+    pub async fn list_operator_users(
+        &self,
+        metadata_key: &str,
+        role_prefix: &str,
+    ) -> Result<BTreeMap<String, User>> {
+        let query = json!({"prefix": {"roles": role_prefix}});
+        let mut users = self.query_users(Some(&query)).await?;
+        users.retain(|_, user| {
+            user.metadata
+                .as_ref()
+                .is_some_and(|m| m.contains_key(metadata_key))
+        });
+        Ok(users)
+    }
+    /// All native roles by name as raw JSON, without the built-in ones.
+    /// Pages through the query API on 8.15+, older clusters return all
+    /// at once.
+    pub async fn list_roles(&self) -> Result<BTreeMap<String, Value>> {
+        match self
+            .query_all("/_security/_query/role", "roles", "name", None)
+            .await?
+        {
+            Some(roles) => Ok(roles
+                .into_iter()
+                .filter_map(|mut role| {
+                    let object = role.as_object_mut()?;
+                    let name = object.remove("name")?.as_str()?.to_string();
+                    object.remove("_sort");
+                    Some((name, role))
+                })
+                .filter(|(_, role)| role["metadata"]["_reserved"] != true)
+                .collect()),
+            None => self.get_all("/_security/role").await,
+        }
+    }
     pub async fn create_user(&self, username: impl Display, user: &User) -> Result<()> {
         let res = self
-            .client
+            .client()
             .post(self.format_url(format!("/_security/user/{}", username)))
             .json(user)
-            .send()
+            .send_traced(self)
             .await?;
         trace!("Status code creating user {}: {}", username, res.status());
         if !res.status().is_success() {
-            return Err(ElasticError::Custom(format!(
-                "Error creating user {}: {}",
-                username,
-                res.text().await?
-            ))
-            .into());
+            return Err(
+                response_error(res, format!("Error creating user {}", username))
+                    .await?
+                    .into(),
+            );
         }
         Ok(())
     }
     pub async fn get_user(&self, username: impl Display) -> Result<Option<User>> {
         let res = self
-            .client
+            .client()
             .get(self.format_url(format!("/_security/user/{}", username)))
-            .send()
+            .send_traced(self)
             .await?;
         if res.status().as_u16() == 404 {
             return Ok(None);
         }
         if !res.status().is_success() {
-            return Err(ElasticError::Custom(format!(
-                "Error getting user {}: {}",
-                username,
-                res.text().await?
-            ))
-            .into());
+            return Err(
+                response_error(res, format!("Error getting user {}", username))
+                    .await?
+                    .into(),
+            );
         }
         let body = res.text().await?;
-        let mut user_map: HashMap<String, User> = serde_json::from_str(body.as_str()).context(
-            format!("Failed to parse user into user map format: {}", body),
-        )?;
+        let mut user_map: HashMap<String, User> =
+            serde_json::from_str(body.as_str()).context(format!(
+                "Failed to parse user into user map format: {}",
+                excerpt(&body)
+            ))?;
         let user = user_map
             .remove(username.to_string().as_str())
             .ok_or(ElasticError::Custom(format!(
@@ -201,21 +665,321 @@ impl ElasticAdmin {
     }
     pub async fn delete_user(&self, name: impl Display) -> Result<bool> {
         let res = self
-            .client
+            .client()
             .delete(self.format_url(format!("/_security/user/{}", name)))
-            .send()
+            .send_traced(self)
             .await?;
         trace!("Status code of deleting user {}: {}", name, res.status());
         if res.status().as_u16() == 404 {
             return Ok(false);
         }
         if !res.status().is_success() {
-            return Err(ElasticError::Custom(format!(
-                "Error deleting user: {}",
-                res.text().await?
-            ))
-            .into());
+            return Err(response_error(res, "Error deleting user").await?.into());
         }
         Ok(true)
     }
+    /// Disable a user, keeping it. False if it does not exist.
+    pub async fn disable_user(&self, name: impl Display) -> Result<bool> {
+        let res = self
+            .client()
+            .put(self.format_url(format!("/_security/user/{}/_disable", name)))
+            .send_traced(self)
+            .await?;
+        trace!("Status code of disabling user {}: {}", name, res.status());
+        if res.status().as_u16() == 404 {
+            return Ok(false);
+        }
+        if !res.status().is_success() {
+            return Err(response_error(res, "Error disabling user").await?.into());
+        }
+        Ok(true)
+    }
+    /// Create an API key on behalf of the user, limited to the role,
+    /// instead of all roles of the user.
+    pub async fn grant_api_key(
+        &self,
+        username: impl Display,
+        password: &Sensitive,
+        name: impl Display,
+        role_name: &str,
+        role: &Role,
+    ) -> Result<ApiKey> {
+        let res = self
+            .client()
+            .post(self.format_url("/_security/api_key/grant"))
+            .json(&serde_json::json!({
+                "grant_type": "password",
+                "username": username.to_string(),
+                "password": password.expose(),
+                "api_key": {
+                    "name": name.to_string(),
+                    "role_descriptors": {role_name: role.descriptor()},
+                },
+            }))
+            .send_traced(self)
+            .await?;
+        trace!(
+            "Status code granting API key for {}: {}",
+            username,
+            res.status()
+        );
+        if !res.status().is_success() {
+            return Err(
+                response_error(res, format!("Error granting API key for {}", username))
+                    .await?
+                    .into(),
+            );
+        }
+        Ok(res.json().await?)
+    }
+    /// Whether the API key exists and is neither invalidated nor expired.
+    pub async fn api_key_valid(&self, id: impl Display) -> Result<bool> {
+        let res = self
+            .client()
+            .get(self.format_url(format!("/_security/api_key?id={}", id)))
+            .send_traced(self)
+            .await?;
+        if res.status().as_u16() == 404 {
+            return Ok(false);
+        }
+        if !res.status().is_success() {
+            return Err(response_error(res, format!("Error getting API key {}", id))
+                .await?
+                .into());
+        }
+        let body: serde_json::Value = res.json().await?;
+        let now_millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Ok(body["api_keys"].as_array().is_some_and(|keys| {
+            keys.iter().any(|key| {
+                key["invalidated"] == false
+                    && !matches!(key["expiration"].as_u64(), Some(e) if e <= now_millis)
+            })
+        }))
+    }
+    /// Invalidate API keys by ID, or all API keys of a user.
+    pub async fn invalidate_api_keys(&self, query: serde_json::Value) -> Result<()> {
+        let res = self
+            .client()
+            .delete(self.format_url("/_security/api_key"))
+            .json(&query)
+            .send_traced(self)
+            .await?;
+        trace!("Status code invalidating API keys: {}", res.status());
+        if !res.status().is_success() {
+            return Err(response_error(res, "Error invalidating API keys")
+                .await?
+                .into());
+        }
+        Ok(())
+    }
+    pub async fn get_role_mapping(&self, name: impl Display) -> Result<Option<serde_json::Value>> {
+        let res = self
+            .client()
+            .get(self.format_url(format!("/_security/role_mapping/{}", name)))
+            .send_traced(self)
+            .await?;
+        // 410 if the API is not available, e.g. on Serverless
+        if matches!(res.status().as_u16(), 404 | 410) {
+            return Ok(None);
+        }
+        if !res.status().is_success() {
+            return Err(
+                response_error(res, format!("Error getting role mapping {}", name))
+                    .await?
+                    .into(),
+            );
+        }
+        let mut body: serde_json::Value = res.json().await?;
+        Ok(body.get_mut(name.to_string()).map(serde_json::Value::take))
+    }
+    /// Create or overwrite a role mapping.
+    pub async fn put_role_mapping(
+        &self,
+        name: impl Display,
+        mapping: &serde_json::Value,
+    ) -> Result<()> {
+        let res = self
+            .client()
+            .put(self.format_url(format!("/_security/role_mapping/{}", name)))
+            .json(mapping)
+            .send_traced(self)
+            .await?;
+        trace!(
+            "Status code putting role mapping {}: {}",
+            name,
+            res.status()
+        );
+        if !res.status().is_success() {
+            return Err(
+                response_error(res, format!("Error putting role mapping {}", name))
+                    .await?
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+    pub async fn delete_role_mapping(&self, name: impl Display) -> Result<bool> {
+        let res = self
+            .client()
+            .delete(self.format_url(format!("/_security/role_mapping/{}", name)))
+            .send_traced(self)
+            .await?;
+        trace!(
+            "Status code of deleting role mapping {}: {}",
+            name,
+            res.status()
+        );
+        if res.status().as_u16() == 404 {
+            return Ok(false);
+        }
+        if !res.status().is_success() {
+            return Err(response_error(res, "Error deleting role mapping")
+                .await?
+                .into());
+        }
+        Ok(true)
+    }
+    /// Whether any index, alias or data stream matches the pattern.
+    pub async fn index_exists(&self, pattern: impl Display) -> Result<bool> {
+        let res = self
+            .client()
+            .get(self.format_url(format!("/_resolve/index/{}?expand_wildcards=all", pattern)))
+            .send_traced(self)
+            .await?;
+        if res.status().as_u16() == 404 {
+            return Ok(false);
+        }
+        if !res.status().is_success() {
+            return Err(
+                response_error(res, format!("Error resolving index {}", pattern))
+                    .await?
+                    .into(),
+            );
+        }
+        let body: serde_json::Value = res.json().await?;
+        Ok(["indices", "aliases", "data_streams"]
+            .iter()
+            .any(|kind| body[kind].as_array().is_some_and(|a| !a.is_empty())))
+    }
+    /// Create an index with settings, mappings and aliases of the body.
+    /// False if it already existed.
+    pub async fn create_index(&self, name: impl Display, body: &serde_json::Value) -> Result<bool> {
+        let res = self
+            .client()
+            .put(self.format_url(format!("/{}", name)))
+            .json(body)
+            .send_traced(self)
+            .await?;
+        trace!("Status code creating index {}: {}", name, res.status());
+        if res.status().is_success() {
+            return Ok(true);
+        }
+        let status = res.status().as_u16();
+        let text = error_body(res).await?;
+        if text.contains("resource_already_exists_exception") {
+            return Ok(false);
+        }
+        let context = format!("Error creating index {}", name);
+        Err(ElasticError::from_response(status, &text, context).into())
+    }
+    /// Index a document with an ID generated by Elasticsearch.
+    pub async fn index_document(
+        &self,
+        index: impl Display,
+        document: &impl Serialize,
+    ) -> Result<()> {
+        let res = self
+            .client()
+            .post(self.format_url(format!("/{}/_doc", index)))
+            .json(document)
+            .send_traced(self)
+            .await?;
+        trace!("Status code indexing into {}: {}", index, res.status());
+        if !res.status().is_success() {
+            return Err(
+                response_error(res, format!("Error indexing document into {}", index))
+                    .await?
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_joined_with_base_path() {
+        let elastic = ElasticAdmin::new(
+            "https://gateway.example.com/es//",
+            "elastic",
+            "pw",
+            ConnectionConfig::default(),
+        );
+        assert_eq!(elastic.url, "https://gateway.example.com/es");
+        assert_eq!(
+            elastic
+                .format_url("/_security/user/a b%?refresh=true")
+                .as_str(),
+            "https://gateway.example.com/es/_security/user/a%20b%25?refresh=true"
+        );
+        let root = ElasticAdmin::new(
+            "http://elastic:9200",
+            "elastic",
+            "pw",
+            ConnectionConfig::default(),
+        );
+        assert_eq!(root.url, "http://elastic:9200");
+        assert_eq!(root.format_url("/").as_str(), "http://elastic:9200/");
+        assert!(base_url("elastic:9200").is_err());
+        assert!(base_url("https://user:pw@elastic:9200").is_err());
+        assert!(base_url("https://elastic:9200/?pretty").is_err());
+    }
+
+    #[test]
+    fn identity_in_user_agent() {
+        let mut connection = ConnectionConfig::default();
+        assert_eq!(
+            user_agent(&connection),
+            format!("ext-elasticsearch-operator/{}", VERSION)
+        );
+        connection.instance_id = Some("prod".into());
+        connection.pod = Some("eeops/eeops-7d9f".into());
+        assert_eq!(
+            user_agent(&connection),
+            format!(
+                "ext-elasticsearch-operator/{} (instance=prod; pod=eeops/eeops-7d9f)",
+                VERSION
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_repeated_connection_errors() {
+        // Nothing listens on the port once the listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let elastic = ElasticAdmin::new(
+            &format!("http://127.0.0.1:{}", port),
+            "elastic",
+            "mock",
+            ConnectionConfig {
+                reconnect_after: 2,
+                ..Default::default()
+            },
+        );
+        let failures = || elastic.connection_failures.load(Ordering::Relaxed);
+        assert!(elastic.get_self().await.is_err());
+        assert_eq!(failures(), 1);
+        assert!(elastic.get_self().await.is_err());
+        assert_eq!(failures(), 0);
+    }
 }
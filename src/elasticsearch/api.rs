@@ -0,0 +1,117 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{ApiKey, ElasticAdmin, ElasticError, Role, Sensitive, User};
+
+/// Operations the reconciliation needs from Elasticsearch.
+/// Implemented by ElasticAdmin, and by an in-memory mock for tests.
+#[async_trait]
+pub trait ElasticsearchApi: Send + Sync {
+    /// Base URL handed out to applications.
+    fn url(&self) -> &str;
+    async fn get_role(&self, name: &str) -> Result<Option<Role>>;
+    /// Create or overwrite a role.
+    async fn create_role(&self, name: &str, role: &Role) -> Result<()>;
+    async fn delete_role(&self, name: &str) -> Result<bool>;
+    async fn get_user(&self, username: &str) -> Result<Option<User>>;
+    /// Create or overwrite a user.
+    async fn create_user(&self, username: &str, user: &User) -> Result<()>;
+    async fn delete_user(&self, username: &str) -> Result<bool>;
+    async fn disable_user(&self, username: &str) -> Result<bool>;
+    async fn get_role_mapping(&self, name: &str) -> Result<Option<serde_json::Value>>;
+    /// Create or overwrite a role mapping.
+    async fn put_role_mapping(&self, name: &str, mapping: &serde_json::Value) -> Result<()>;
+    async fn delete_role_mapping(&self, name: &str) -> Result<bool>;
+    /// Ok if Elasticsearch accepts the login, WrongCredentials if not.
+    async fn check_login(&self, username: &str, password: &Sensitive) -> Result<(), ElasticError>;
+    async fn index_document(&self, index: &str, document: serde_json::Value) -> Result<()>;
+    /// Whether any index, alias or data stream matches the pattern.
+    async fn index_exists(&self, pattern: &str) -> Result<bool>;
+    /// Create an index, false if it already existed.
+    async fn create_index(&self, name: &str, body: &serde_json::Value) -> Result<bool>;
+    /// Create an API key on behalf of the user, limited to the role.
+    async fn grant_api_key(
+        &self,
+        username: &str,
+        password: &Sensitive,
+        name: &str,
+        role_name: &str,
+        role: &Role,
+    ) -> Result<ApiKey>;
+    async fn api_key_valid(&self, id: &str) -> Result<bool>;
+    async fn invalidate_api_key(&self, id: &str) -> Result<()>;
+    async fn invalidate_user_api_keys(&self, username: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl ElasticsearchApi for ElasticAdmin {
+    fn url(&self) -> &str {
+        &self.url
+    }
+    async fn get_role(&self, name: &str) -> Result<Option<Role>> {
+        ElasticAdmin::get_role(self, name).await
+    }
+    async fn create_role(&self, name: &str, role: &Role) -> Result<()> {
+        ElasticAdmin::create_role(self, name, role).await
+    }
+    async fn delete_role(&self, name: &str) -> Result<bool> {
+        ElasticAdmin::delete_role(self, name).await
+    }
+    async fn get_user(&self, username: &str) -> Result<Option<User>> {
+        ElasticAdmin::get_user(self, username).await
+    }
+    async fn create_user(&self, username: &str, user: &User) -> Result<()> {
+        ElasticAdmin::create_user(self, username, user).await
+    }
+    async fn delete_user(&self, username: &str) -> Result<bool> {
+        ElasticAdmin::delete_user(self, username).await
+    }
+    async fn disable_user(&self, username: &str) -> Result<bool> {
+        ElasticAdmin::disable_user(self, username).await
+    }
+    async fn get_role_mapping(&self, name: &str) -> Result<Option<serde_json::Value>> {
+        ElasticAdmin::get_role_mapping(self, name).await
+    }
+    async fn put_role_mapping(&self, name: &str, mapping: &serde_json::Value) -> Result<()> {
+        ElasticAdmin::put_role_mapping(self, name, mapping).await
+    }
+    async fn delete_role_mapping(&self, name: &str) -> Result<bool> {
+        ElasticAdmin::delete_role_mapping(self, name).await
+    }
+    async fn check_login(&self, username: &str, password: &Sensitive) -> Result<(), ElasticError> {
+        self.clone_with_new_login(username, password)
+            .get_self()
+            .await
+            .map(|_| ())
+    }
+    async fn index_document(&self, index: &str, document: serde_json::Value) -> Result<()> {
+        ElasticAdmin::index_document(self, index, &document).await
+    }
+    async fn index_exists(&self, pattern: &str) -> Result<bool> {
+        ElasticAdmin::index_exists(self, pattern).await
+    }
+    async fn create_index(&self, name: &str, body: &serde_json::Value) -> Result<bool> {
+        ElasticAdmin::create_index(self, name, body).await
+    }
+    async fn grant_api_key(
+        &self,
+        username: &str,
+        password: &Sensitive,
+        name: &str,
+        role_name: &str,
+        role: &Role,
+    ) -> Result<ApiKey> {
+        ElasticAdmin::grant_api_key(self, username, password, name, role_name, role).await
+    }
+    async fn api_key_valid(&self, id: &str) -> Result<bool> {
+        ElasticAdmin::api_key_valid(self, id).await
+    }
+    async fn invalidate_api_key(&self, id: &str) -> Result<()> {
+        self.invalidate_api_keys(serde_json::json!({ "ids": [id] }))
+            .await
+    }
+    async fn invalidate_user_api_keys(&self, username: &str) -> Result<()> {
+        self.invalidate_api_keys(serde_json::json!({ "username": username }))
+            .await
+    }
+}
@@ -0,0 +1,21 @@
+use serde::Deserialize;
+
+use super::Sensitive;
+
+/// API key as returned on creation. The key itself
+/// can't be retrieved later on.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub id: String,
+    /// Base64 of `id:api_key`, as used in `Authorization: ApiKey ...`.
+    pub encoded: Sensitive,
+}
+
+impl ApiKey {
+    /// ID of an encoded API key.
+    pub fn id_of_encoded(encoded: &str) -> Option<String> {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+        decoded.split_once(':').map(|(id, _)| id.to_string())
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{
+    header::{self, HeaderValue},
+    Request,
+};
+
+/// Global like trace_http, so temporary logins compress alike.
+/// Set before the first ElasticAdmin is created, as responses are
+/// only decompressed by clients built afterwards.
+static COMPRESSION: AtomicBool = AtomicBool::new(false);
+
+/// Smaller bodies are sent as they are, gzip would barely shrink them.
+const MIN_COMPRESSED_BODY: usize = 1024;
+
+/// Gzip request bodies and ask for gzipped responses.
+pub fn set_compression(enabled: bool) {
+    COMPRESSION.store(enabled, Ordering::Relaxed);
+}
+
+pub(super) fn enabled() -> bool {
+    COMPRESSION.load(Ordering::Relaxed)
+}
+
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+/// Replace a large enough body by its gzip encoding.
+pub(super) fn compress(request: &mut Request) {
+    let Some(body) = request.body().and_then(|b| b.as_bytes()) else {
+        return;
+    };
+    if body.len() < MIN_COMPRESSED_BODY {
+        return;
+    }
+    // Writing into memory does not fail
+    let Ok(compressed) = gzip(body) else {
+        return;
+    };
+    *request.body_mut() = Some(compressed.into());
+    request
+        .headers_mut()
+        .insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use reqwest::{Method, Url};
+
+    use super::*;
+
+    #[test]
+    fn large_bodies_gzipped() {
+        let url = Url::parse("http://localhost:9200/_security/role/r").unwrap();
+        let mut small = Request::new(Method::PUT, url.clone());
+        *small.body_mut() = Some("{}".into());
+        compress(&mut small);
+        assert!(small.headers().get(header::CONTENT_ENCODING).is_none());
+
+        let document = serde_json::json!({"indices": [{"names": vec!["logs-*"; 200]}]}).to_string();
+        let mut large = Request::new(Method::PUT, url);
+        *large.body_mut() = Some(document.clone().into());
+        compress(&mut large);
+        assert_eq!(large.headers()[header::CONTENT_ENCODING], "gzip");
+        let compressed = large.body().and_then(|b| b.as_bytes()).unwrap();
+        assert!(compressed.len() < document.len());
+        let mut decoded = String::new();
+        GzDecoder::new(compressed)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, document);
+    }
+}
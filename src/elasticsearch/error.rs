@@ -1,5 +1,8 @@
+use serde::Deserialize;
 use thiserror::Error;
 
+use super::sensitive::excerpt;
+
 #[derive(Error, Debug)]
 pub enum ElasticError {
     #[error("{0}")]
@@ -8,6 +11,107 @@ pub enum ElasticError {
     WrongCredentials,
     #[error("The provided login does work, but the user is missing the superuser credentials.")]
     NotSuperuser,
+    /// The security features are disabled on the cluster.
+    #[error("{0}")]
+    SecurityDisabled(String),
+    /// Concurrent modification, e.g. of the security index.
+    #[error("{0}")]
+    VersionConflict(String),
+    /// The login lacks the privileges for the request.
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    IndexNotFound(String),
     #[error("An unexpected error occurred: {0}")]
     Custom(String),
 }
+
+/// Error document of Elasticsearch, e.g.
+/// {"error": {"type": "security_exception", "reason": "..."}, "status": 403}
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorCause,
+}
+
+#[derive(Deserialize)]
+struct ErrorCause {
+    #[serde(rename = "type")]
+    type_: String,
+    reason: Option<String>,
+    #[serde(default)]
+    root_cause: Vec<ErrorCause>,
+}
+
+impl ElasticError {
+    /// Classify the (redacted) body of a failed response.
+    /// `context` describes the request, e.g. "Error creating user app".
+    pub fn from_response(status: u16, body: &str, context: impl AsRef<str>) -> Self {
+        let context = context.as_ref();
+        let Ok(ErrorResponse { error }) = serde_json::from_str::<ErrorResponse>(body) else {
+            return ElasticError::Custom(format!("{}: {}", context, excerpt(body)));
+        };
+        // The root cause names the actual failure, e.g. of a wrapped exception
+        let cause = error.root_cause.first().unwrap_or(&error);
+        let message = format!(
+            "{}: {}",
+            context,
+            excerpt(cause.reason.as_deref().unwrap_or(&cause.type_))
+        );
+        let reason = cause.reason.as_deref().unwrap_or_default().to_lowercase();
+        let security_disabled = [
+            "security must be explicitly enabled",
+            "security is not enabled",
+            "security is disabled",
+        ]
+        .iter()
+        .any(|hint| reason.contains(hint));
+        match cause.type_.as_str() {
+            "security_exception" if security_disabled => ElasticError::SecurityDisabled(message),
+            "version_conflict_engine_exception" => ElasticError::VersionConflict(message),
+            "index_not_found_exception" => ElasticError::IndexNotFound(message),
+            _ if status == 409 => ElasticError::VersionConflict(message),
+            _ if status == 403 => ElasticError::Forbidden(message),
+            _ => ElasticError::Custom(format!("{}: {}", context, excerpt(body))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_bodies_classified() {
+        let forbidden = r#"{"error": {"root_cause": [{"type": "security_exception",
+            "reason": "action [cluster:admin/xpack/security/user/put] is unauthorized"}],
+            "type": "security_exception", "reason": "unauthorized"}, "status": 403}"#;
+        assert!(matches!(
+            ElasticError::from_response(403, forbidden, "Error creating user app"),
+            ElasticError::Forbidden(m) if m == "Error creating user app: action \
+                [cluster:admin/xpack/security/user/put] is unauthorized"
+        ));
+        let disabled = r#"{"error": {"type": "security_exception", "reason":
+            "Security must be explicitly enabled when using a [basic] license."},
+            "status": 500}"#;
+        assert!(matches!(
+            ElasticError::from_response(500, disabled, "x"),
+            ElasticError::SecurityDisabled(_)
+        ));
+        let disabled = r#"{"error": {"type": "security_exception", "reason":
+            "current license is non-compliant for [security], security is not enabled"}}"#;
+        assert!(matches!(
+            ElasticError::from_response(403, disabled, "x"),
+            ElasticError::SecurityDisabled(_)
+        ));
+        let missing = r#"{"error": {"type": "index_not_found_exception",
+            "reason": "no such index [audit]"}, "status": 404}"#;
+        assert!(matches!(
+            ElasticError::from_response(404, missing, "x"),
+            ElasticError::IndexNotFound(_)
+        ));
+        assert!(matches!(
+            ElasticError::from_response(502, "Bad Gateway", "x"),
+            ElasticError::Custom(m) if m == "x: Bad Gateway"
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+use super::{ApiKey, ElasticError, ElasticsearchApi, Role, Sensitive, User};
+
+/// In-memory stand-in for Elasticsearch.
+#[derive(Default)]
+pub struct MockElastic {
+    pub roles: Mutex<HashMap<String, Role>>,
+    /// Users including their password, which Elasticsearch never returns.
+    pub users: Mutex<HashMap<String, User>>,
+    pub documents: Mutex<Vec<(String, serde_json::Value)>>,
+    /// Owner and validity by API key ID.
+    pub api_keys: Mutex<HashMap<String, (String, bool)>>,
+    /// Role descriptors by API key ID.
+    pub api_key_roles: Mutex<HashMap<String, serde_json::Value>>,
+    pub role_mappings: Mutex<HashMap<String, serde_json::Value>>,
+    /// Body of the created indices by name.
+    pub indices: Mutex<HashMap<String, serde_json::Value>>,
+}
+
+#[async_trait]
+impl ElasticsearchApi for MockElastic {
+    fn url(&self) -> &str {
+        "http://elastic.mock:9200"
+    }
+    async fn get_role(&self, name: &str) -> Result<Option<Role>> {
+        Ok(self.roles.lock().unwrap().get(name).cloned())
+    }
+    async fn create_role(&self, name: &str, role: &Role) -> Result<()> {
+        self.roles
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), role.clone());
+        Ok(())
+    }
+    async fn delete_role(&self, name: &str) -> Result<bool> {
+        Ok(self.roles.lock().unwrap().remove(name).is_some())
+    }
+    async fn get_user(&self, username: &str) -> Result<Option<User>> {
+        Ok(self.users.lock().unwrap().get(username).map(|user| User {
+            password: None,
+            ..user.clone()
+        }))
+    }
+    async fn create_user(&self, username: &str, user: &User) -> Result<()> {
+        let mut users = self.users.lock().unwrap();
+        // Like Elasticsearch, updates without password keep it
+        let password = match &user.password {
+            Some(password) => Some(password.clone()),
+            None => users.get(username).and_then(|u| u.password.clone()),
+        };
+        users.insert(
+            username.to_string(),
+            User {
+                password,
+                ..user.clone()
+            },
+        );
+        Ok(())
+    }
+    async fn delete_user(&self, username: &str) -> Result<bool> {
+        Ok(self.users.lock().unwrap().remove(username).is_some())
+    }
+    async fn disable_user(&self, username: &str) -> Result<bool> {
+        match self.users.lock().unwrap().get_mut(username) {
+            Some(user) => {
+                user.enabled = Some(false);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    async fn get_role_mapping(&self, name: &str) -> Result<Option<serde_json::Value>> {
+        Ok(self.role_mappings.lock().unwrap().get(name).cloned())
+    }
+    async fn put_role_mapping(&self, name: &str, mapping: &serde_json::Value) -> Result<()> {
+        self.role_mappings
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), mapping.clone());
+        Ok(())
+    }
+    async fn delete_role_mapping(&self, name: &str) -> Result<bool> {
+        Ok(self.role_mappings.lock().unwrap().remove(name).is_some())
+    }
+    async fn check_login(&self, username: &str, password: &Sensitive) -> Result<(), ElasticError> {
+        match self.users.lock().unwrap().get(username) {
+            Some(user)
+                if user.password.as_ref() == Some(password) && user.enabled != Some(false) =>
+            {
+                Ok(())
+            }
+            _ => Err(ElasticError::WrongCredentials),
+        }
+    }
+    async fn index_document(&self, index: &str, document: serde_json::Value) -> Result<()> {
+        self.documents
+            .lock()
+            .unwrap()
+            .push((index.to_string(), document));
+        Ok(())
+    }
+    async fn index_exists(&self, pattern: &str) -> Result<bool> {
+        let prefix = pattern.trim_end_matches('*');
+        Ok(self
+            .indices
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|name| name.starts_with(prefix)))
+    }
+    async fn create_index(&self, name: &str, body: &serde_json::Value) -> Result<bool> {
+        let mut indices = self.indices.lock().unwrap();
+        if indices.contains_key(name) {
+            return Ok(false);
+        }
+        indices.insert(name.to_string(), body.clone());
+        Ok(true)
+    }
+    async fn grant_api_key(
+        &self,
+        username: &str,
+        password: &Sensitive,
+        _name: &str,
+        role_name: &str,
+        role: &Role,
+    ) -> Result<ApiKey> {
+        self.check_login(username, password).await?;
+        let mut api_keys = self.api_keys.lock().unwrap();
+        let id = format!("key-{}", api_keys.len());
+        api_keys.insert(id.clone(), (username.to_string(), true));
+        self.api_key_roles.lock().unwrap().insert(
+            id.clone(),
+            serde_json::json!({ role_name: role.descriptor() }),
+        );
+        Ok(ApiKey {
+            encoded: STANDARD.encode(format!("{}:secret", id)).into(),
+            id,
+        })
+    }
+    async fn api_key_valid(&self, id: &str) -> Result<bool> {
+        Ok(self
+            .api_keys
+            .lock()
+            .unwrap()
+            .get(id)
+            .is_some_and(|(_, valid)| *valid))
+    }
+    async fn invalidate_api_key(&self, id: &str) -> Result<()> {
+        if let Some((_, valid)) = self.api_keys.lock().unwrap().get_mut(id) {
+            *valid = false;
+        }
+        Ok(())
+    }
+    async fn invalidate_user_api_keys(&self, username: &str) -> Result<()> {
+        for (owner, valid) in self.api_keys.lock().unwrap().values_mut() {
+            if owner == username {
+                *valid = false;
+            }
+        }
+        Ok(())
+    }
+}
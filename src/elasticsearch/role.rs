@@ -1,14 +1,32 @@
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
 
 use serde::{ser::SerializeSeq, Deserialize, Serialize};
 
-use crate::UserPermissions;
+use crate::{changes::Change, UserPermissions};
 
-#[derive(Eq, PartialEq, Debug)]
+/// Role metadata key holding the ElasticsearchUsers
+/// (`namespace/name`) which currently use the role.
+pub const ROLE_REFERENCES_KEY: &str = "eeops_referenced_by";
+/// Role metadata key mapping each reference to the UID of the
+/// ElasticsearchUser, to tell apart CRs recreated with the same name.
+pub const ROLE_REFERENCE_UIDS_KEY: &str = "eeops_referenced_by_uid";
+
+#[derive(Eq, PartialEq, Debug, Clone)]
 pub struct Privileges {
     read: bool,
     write: bool,
     create: bool,
+    monitor: bool,
+    view_index_metadata: bool,
+    maintenance: bool,
+    manage: bool,
+    create_index: bool,
+    auto_configure: bool,
+    /// Privileges without a flag, passed verbatim, see spec.privilegesRaw.
+    other: BTreeSet<String>,
 }
 
 impl From<UserPermissions> for Privileges {
@@ -33,17 +51,7 @@ impl Default for Privileges {
 impl Display for Privileges {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // e.g. Read, Write
-        let arr = [
-            ("read", self.read),
-            ("write", self.write),
-            ("create", self.create),
-        ];
-        let s: Vec<&'static str> = arr
-            .iter()
-            .filter(|(_, cond)| *cond)
-            .map(|(name, _)| *name)
-            .collect();
-        write!(f, "{}", s.join(", "))
+        write!(f, "{}", self.names().join(", "))
     }
 }
 
@@ -53,7 +61,54 @@ impl Privileges {
             read: false,
             write: false,
             create: false,
+            monitor: false,
+            view_index_metadata: false,
+            maintenance: false,
+            manage: false,
+            create_index: false,
+            auto_configure: false,
+            other: BTreeSet::new(),
+        }
+    }
+    /// Privileges by their Elasticsearch names, known or not.
+    pub fn raw(names: &[String]) -> Self {
+        let mut privileges = Self::new();
+        for name in names {
+            match name.as_str() {
+                "read" => privileges.read = true,
+                "write" => privileges.write = true,
+                "create" => privileges.create = true,
+                "monitor" => privileges.monitor = true,
+                "view_index_metadata" => privileges.view_index_metadata = true,
+                "maintenance" => privileges.maintenance = true,
+                "manage" => privileges.manage = true,
+                "create_index" => privileges.create_index = true,
+                "auto_configure" => privileges.auto_configure = true,
+                other => {
+                    privileges.other.insert(other.to_string());
+                }
+            }
         }
+        privileges
+    }
+    /// Elasticsearch names of the enabled privileges.
+    pub fn names(&self) -> Vec<&str> {
+        [
+            ("read", self.read),
+            ("write", self.write),
+            ("create", self.create),
+            ("monitor", self.monitor),
+            ("view_index_metadata", self.view_index_metadata),
+            ("maintenance", self.maintenance),
+            ("manage", self.manage),
+            ("create_index", self.create_index),
+            ("auto_configure", self.auto_configure),
+        ]
+        .iter()
+        .filter(|(_, cond)| *cond)
+        .map(|(name, _)| *name)
+        .chain(self.other.iter().map(String::as_str))
+        .collect()
     }
     pub fn enable_read(mut self) -> Self {
         self.read = true;
@@ -67,9 +122,36 @@ impl Privileges {
         self.create = true;
         self
     }
+    pub fn enable_monitor(mut self) -> Self {
+        self.monitor = true;
+        self
+    }
+    pub fn enable_view_index_metadata(mut self) -> Self {
+        self.view_index_metadata = true;
+        self
+    }
+    pub fn enable_maintenance(mut self) -> Self {
+        self.maintenance = true;
+        self
+    }
+    pub fn enable_manage(mut self) -> Self {
+        self.manage = true;
+        self
+    }
+    pub fn enable_create_index(mut self) -> Self {
+        self.create_index = true;
+        self
+    }
+    pub fn enable_auto_configure(mut self) -> Self {
+        self.auto_configure = true;
+        self
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names().is_empty()
+    }
 }
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct IndexPermission {
     pub names: Vec<String>,
     pub privileges: Privileges,
@@ -82,15 +164,166 @@ impl Display for IndexPermission {
     }
 }
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+/// Applications a global privilege applies to.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct GlobalApplications {
+    pub applications: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct GlobalApplicationPrivileges {
+    pub manage: GlobalApplications,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+pub struct GlobalProfilePrivileges {
+    pub write: GlobalApplications,
+}
+
+/// Privileges not bound to indices, e.g. to manage
+/// the application privileges of some applications.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Default)]
+pub struct GlobalPrivileges {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<GlobalApplicationPrivileges>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<GlobalProfilePrivileges>,
+}
+
+impl Display for GlobalPrivileges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(application) = &self.application {
+            let apps = application.manage.applications.join(", ");
+            parts.push(format!("manage applications [{}]", apps));
+        }
+        if let Some(profile) = &self.profile {
+            let apps = profile.write.applications.join(", ");
+            parts.push(format!("write profiles of [{}]", apps));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub struct Role {
     pub indices: Vec<IndexPermission>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global: Option<GlobalPrivileges>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    /// Sections the operator does not manage, e.g. cluster, applications
+    /// or run_as added by an admin, written back as read.
+    #[serde(flatten)]
+    pub unmanaged: BTreeMap<String, serde_json::Value>,
+}
+
+impl Role {
+    /// Privileges and references of old changed by writing this role.
+    pub fn changes(&self, old: &Role) -> Vec<Change> {
+        let mut changes = Vec::new();
+        if self.indices != old.indices {
+            let indices = |r: &Role| {
+                let indices: Vec<String> = r.indices.iter().map(ToString::to_string).collect();
+                indices.join("; ")
+            };
+            changes.push(Change::new(
+                "role.indices",
+                Some(indices(old)),
+                Some(indices(self)),
+            ));
+        }
+        if self.global != old.global {
+            changes.push(Change::new(
+                "role.global",
+                old.global.as_ref(),
+                self.global.as_ref(),
+            ));
+        }
+        if self.references() != old.references() {
+            let references = |r: &Role| r.references().into_iter().collect::<Vec<_>>().join(", ");
+            changes.push(Change::new(
+                "role.references",
+                Some(references(old)),
+                Some(references(self)),
+            ));
+        }
+        changes
+    }
+    /// Role descriptor limiting an API key to this role, without the
+    /// metadata, which only describes the role itself.
+    pub fn descriptor(&self) -> serde_json::Value {
+        let mut descriptor = serde_json::to_value(self).unwrap_or_default();
+        if let Some(object) = descriptor.as_object_mut() {
+            object.remove("metadata");
+            object.remove("transient_metadata");
+        }
+        descriptor
+    }
+    /// Whether both roles grant the same, regardless of their metadata.
+    pub fn same_privileges(&self, other: &Role) -> bool {
+        self.indices == other.indices && self.global == other.global
+    }
+    /// ElasticsearchUsers referencing this role, as stored
+    /// in the role metadata. Empty for roles created
+    /// before references were tracked.
+    pub fn references(&self) -> BTreeSet<String> {
+        self.metadata
+            .get(ROLE_REFERENCES_KEY)
+            .and_then(|v| v.as_array())
+            .map(|refs| {
+                refs.iter()
+                    .filter_map(|r| r.as_str().map(ToString::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    /// UIDs of the referencing ElasticsearchUsers by reference.
+    pub fn reference_uids(&self) -> BTreeMap<String, String> {
+        self.metadata
+            .get(ROLE_REFERENCE_UIDS_KEY)
+            .and_then(|v| v.as_object())
+            .map(|uids| {
+                uids.iter()
+                    .filter_map(|(r, uid)| uid.as_str().map(|uid| (r.clone(), uid.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    fn set_reference_uids(&mut self, uids: BTreeMap<String, String>) {
+        self.metadata.insert(
+            ROLE_REFERENCE_UIDS_KEY.to_string(),
+            uids.into_iter()
+                .map(|(r, uid)| (r, serde_json::Value::from(uid)))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+        );
+    }
+    /// Set the references, UIDs of references
+    /// not contained anymore are dropped.
+    pub fn set_references(&mut self, references: BTreeSet<String>) {
+        let mut uids = self.reference_uids();
+        uids.retain(|r, _| references.contains(r));
+        self.set_reference_uids(uids);
+        self.metadata.insert(
+            ROLE_REFERENCES_KEY.to_string(),
+            serde_json::Value::from(references.into_iter().collect::<Vec<String>>()),
+        );
+    }
+    pub fn set_reference_uid(&mut self, reference: String, uid: String) {
+        let mut uids = self.reference_uids();
+        uids.insert(reference, uid);
+        self.set_reference_uids(uids);
+    }
 }
 
 impl Display for Role {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let indices: Vec<String> = self.indices.iter().map(|x| x.to_string()).collect();
-        write!(f, "{}", indices.join("; "))
+        let mut parts: Vec<String> = self.indices.iter().map(|x| x.to_string()).collect();
+        if let Some(global) = &self.global {
+            parts.push(format!("global {}", global));
+        }
+        write!(f, "{}", parts.join("; "))
     }
 }
 
@@ -99,16 +332,10 @@ impl Serialize for Privileges {
     where
         S: serde::Serializer,
     {
-        let len = self.read as usize + self.write as usize + self.create as usize;
-        let mut seq = serializer.serialize_seq(Some(len))?;
-        if self.read {
-            seq.serialize_element("read")?;
-        }
-        if self.write {
-            seq.serialize_element("write")?;
-        }
-        if self.create {
-            seq.serialize_element("create")?;
+        let names = self.names();
+        let mut seq = serializer.serialize_seq(Some(names.len()))?;
+        for name in names {
+            seq.serialize_element(name)?;
         }
         seq.end()
     }
@@ -119,21 +346,9 @@ impl<'de> Deserialize<'de> for Privileges {
     where
         D: serde::Deserializer<'de>,
     {
-        let permission_array: Vec<String> = Vec::<String>::deserialize(deserializer)?;
-        let mut permissions = Privileges::new();
-        for p in permission_array {
-            match p.as_str() {
-                "read" => permissions.read = true,
-                "write" => permissions.write = true,
-                "create" => permissions.create = true,
-                other => {
-                    return Err(serde::de::Error::invalid_value(
-                        serde::de::Unexpected::Str(other),
-                        &"Permissions must be read, write or create",
-                    ))
-                }
-            }
-        }
-        Ok(permissions)
+        // Roles may carry privileges without a flag, e.g. set by
+        // spec.privilegesRaw, which must not break the comparison.
+        let names = Vec::<String>::deserialize(deserializer)?;
+        Ok(Privileges::raw(&names))
     }
 }
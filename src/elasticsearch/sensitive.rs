@@ -0,0 +1,188 @@
+use std::fmt::{Debug, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Keys whose values never show up in logs or errors.
+const SENSITIVE_KEYS: [&str; 7] = [
+    "password",
+    "password_hash",
+    "api_key",
+    "encoded",
+    "access_token",
+    "refresh_token",
+    "token",
+];
+const REDACTED: &str = "[redacted]";
+/// Authorization schemes, whose credentials proxies may echo.
+const AUTH_SCHEMES: [&str; 3] = ["Basic", "Bearer", "ApiKey"];
+/// Characters of a response body kept in errors, which end up
+/// in the status of ElasticsearchUsers and thereby in etcd.
+const MAX_EXCERPT: usize = 1000;
+
+/// Password, API key or other credential. Debug and Display never
+/// show the value, it is only accessible explicitly via expose.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Sensitive(String);
+
+impl Sensitive {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+pub(super) fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => (),
+    }
+}
+
+/// Replace credentials in a JSON response body, e.g. before it is
+/// embedded into an error. Anything else is returned as is.
+pub fn redact(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact_json(&mut value);
+            value.to_string()
+        }
+        Err(_) => body.to_string(),
+    }
+}
+
+/// Replace the credential following an authorization scheme,
+/// e.g. of an echoed "Authorization: Basic ..." header.
+fn redact_auth_headers(text: &str) -> String {
+    let mut redact_next = false;
+    text.split(' ')
+        .map(|word| {
+            let word = if redact_next && !word.is_empty() {
+                REDACTED
+            } else {
+                word
+            };
+            redact_next = AUTH_SCHEMES.iter().any(|s| word.eq_ignore_ascii_case(s));
+            word
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Bounded, redacted part of a response body for errors and logs.
+/// HTML pages, e.g. of a misconfigured proxy, are reduced to their title.
+pub fn excerpt(body: &str) -> String {
+    let body = redact(body.trim());
+    // Same byte offsets as body, unlike to_lowercase
+    let lowercase = body.to_ascii_lowercase();
+    let body = if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+        let title = lowercase
+            .find("<title>")
+            .map(|start| start + "<title>".len())
+            .and_then(|start| Some(start..start + lowercase[start..].find("</title>")?))
+            .map(|range| body[range].trim().to_string())
+            .unwrap_or_default();
+        format!("HTML page \"{}\" of {} bytes", title, body.len())
+    } else {
+        redact_auth_headers(&body)
+    };
+    match body.char_indices().nth(MAX_EXCERPT) {
+        Some((end, _)) => format!("{}... ({} bytes truncated)", &body[..end], body.len() - end),
+        None => body,
+    }
+}
+
+impl From<String> for Sensitive {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Sensitive {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl From<&Sensitive> for Sensitive {
+    fn from(value: &Sensitive) -> Self {
+        value.clone()
+    }
+}
+
+impl Debug for Sensitive {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Display for Sensitive {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_formatted() {
+        let password = Sensitive::from("hunter2");
+        assert_eq!(
+            format!("{} {:?}", password, password),
+            "[redacted] [redacted]"
+        );
+        assert_eq!(password.expose(), "hunter2");
+        let user = crate::elasticsearch::User {
+            password: Some(password),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", user).contains("hunter2"));
+        assert!(serde_json::to_string(&user).unwrap().contains("hunter2"));
+    }
+
+    #[test]
+    fn credentials_redacted() {
+        let body = r#"{"error":{"reason":"x","password":"pw"},"api_keys":[{"api_key":"k"}]}"#;
+        let redacted: Value = serde_json::from_str(&redact(body)).unwrap();
+        assert_eq!(
+            redacted,
+            serde_json::json!({
+                "error": {"reason": "x", "password": REDACTED},
+                "api_keys": [{"api_key": REDACTED}]
+            })
+        );
+        assert_eq!(redact("Bad Gateway"), "Bad Gateway");
+    }
+
+    #[test]
+    fn excerpts_bounded() {
+        let page = format!(
+            "<!DOCTYPE html><html><head><title>502 Bad Gateway</title></head>{}</html>",
+            "<p>nginx</p>".repeat(100_000)
+        );
+        assert!(excerpt(&page).starts_with("HTML page \"502 Bad Gateway\" of "));
+        // Lowercase of İ is longer
+        let page = "<HTML><META NAME=\"İİİİ\"><TITLE>Fehler</TITLE></HTML>";
+        assert!(excerpt(page).starts_with("HTML page \"Fehler\" of "));
+        let long = "x".repeat(5000);
+        assert_eq!(
+            excerpt(&long),
+            format!("{}... (4000 bytes truncated)", "x".repeat(1000))
+        );
+        assert_eq!(
+            excerpt("denied for Authorization: Basic ZWxhc3RpYzpwdw== at proxy"),
+            "denied for Authorization: Basic [redacted] at proxy"
+        );
+    }
+}
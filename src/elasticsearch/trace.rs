@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use log::trace;
+use reqwest::{header, Request, RequestBuilder, Response, ResponseBuilderExt};
+use serde_json::Value;
+
+use super::{compression, sensitive::redact_json, ElasticAdmin};
+use crate::correlation;
+
+/// Global like the log level, as every ElasticAdmin,
+/// including temporary logins, should trace alike.
+static TRACE_HTTP: AtomicBool = AtomicBool::new(false);
+
+/// Log the bodies of all requests to Elasticsearch at trace level.
+pub fn set_trace_http(enabled: bool) {
+    TRACE_HTTP.store(enabled, Ordering::Relaxed);
+}
+
+/// Body for the logs. Only JSON is shown, as anything
+/// else can't be redacted reliably.
+pub(super) fn redact_body(body: &[u8]) -> String {
+    if body.is_empty() {
+        return String::new();
+    }
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact_json(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!("<{} bytes>", body.len()),
+    }
+}
+
+#[async_trait]
+pub(super) trait SendTraced {
+    /// Like send, but logs request and response if enabled, gzips large
+    /// bodies with compression enabled and passes the correlation ID of
+    /// the reconcile as X-Opaque-Id. Connection errors are counted by
+    /// the admin, to reconnect if they repeat.
+    async fn send_traced(self, admin: &ElasticAdmin) -> reqwest::Result<Response>;
+}
+
+#[async_trait]
+impl SendTraced for RequestBuilder {
+    async fn send_traced(self, admin: &ElasticAdmin) -> reqwest::Result<Response> {
+        let result = send(self).await;
+        admin.record_connection(&result);
+        result
+    }
+}
+
+/// Send, tracing and compressing if enabled.
+async fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    let request = match correlation::current() {
+        Some(id) => request.header("X-Opaque-Id", id),
+        None => request,
+    };
+    let tracing = TRACE_HTTP.load(Ordering::Relaxed) && log::log_enabled!(log::Level::Trace);
+    if !tracing && !compression::enabled() {
+        return request.send().await;
+    }
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    if !tracing {
+        compression::compress(&mut request);
+        return client.execute(request).await;
+    }
+    trace_request(&mut request);
+    let res = client.execute(request).await?;
+    let status = res.status();
+    let url = res.url().clone();
+    let headers = res.headers().clone();
+    let body = res.bytes().await?;
+    trace!(
+        "Elasticsearch response {} {}: {}",
+        status,
+        url,
+        redact_body(&body)
+    );
+    // The body is consumed, hand a copy to the caller
+    let mut builder = hyper::Response::builder().status(status).url(url);
+    if let Some(h) = builder.headers_mut() {
+        *h = headers;
+    }
+    Ok(builder
+        .body(body)
+        .expect("Unexpected error in building HTTP response")
+        .into())
+}
+
+/// Log the request, then compress it if enabled, as it is
+/// logged uncompressed to be readable.
+fn trace_request(request: &mut Request) {
+    // Default headers, including the login, are added by the client
+    let authorization = if request.headers().contains_key(header::AUTHORIZATION) {
+        " (Authorization: [redacted])"
+    } else {
+        ""
+    };
+    trace!(
+        "Elasticsearch request {} {}{}: {}",
+        request.method(),
+        request.url(),
+        authorization,
+        redact_body(
+            request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .unwrap_or_default()
+        )
+    );
+    if compression::enabled() {
+        compression::compress(request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_json_logged() {
+        assert_eq!(
+            redact_body(br#"{"password":"pw","roles":["r"]}"#),
+            r#"{"password":"[redacted]","roles":["r"]}"#
+        );
+        assert_eq!(redact_body(b"user:password"), "<13 bytes>");
+    }
+
+    #[test]
+    fn traced_uncompressed_without_compression() {
+        let url = reqwest::Url::parse("http://localhost:9200/_security/role/r").unwrap();
+        let document = serde_json::json!({"indices": [{"names": vec!["logs-*"; 200]}]}).to_string();
+        let mut request = Request::new(reqwest::Method::PUT, url);
+        *request.body_mut() = Some(document.clone().into());
+        trace_request(&mut request);
+        assert!(request.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(
+            request.body().and_then(|b| b.as_bytes()),
+            Some(document.as_bytes())
+        );
+    }
+}
@@ -2,48 +2,68 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq)]
+use super::Sensitive;
+use crate::changes::Change;
+
+#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone)]
 pub struct User {
-    pub password: Option<String>,
+    pub password: Option<Sensitive>,
     pub roles: Vec<String>,
     pub full_name: Option<String>,
     pub email: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(skip_deserializing)]
-    pub metadata: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, serde_json::Value>>,
 }
 
 impl User {
     pub fn is_same(&self, old: &Self) -> bool {
         self.roles == old.roles && self.full_name == old.full_name && self.email == old.email
     }
-    pub fn delta_string(&self, old: &Self) -> Option<String> {
-        let mut diffs: Vec<String> = Vec::new();
+    /// Fields of old changed by writing this user.
+    pub fn changes(&self, old: &Self) -> Vec<Change> {
+        let mut changes = Vec::new();
         if self.roles != old.roles {
-            diffs.push(format!(
-                "[Roles {} => {}]",
-                self.roles.join(", "),
-                old.roles.join(", ")
+            changes.push(Change::new(
+                "user.roles",
+                Some(old.roles.join(", ")),
+                Some(self.roles.join(", ")),
             ));
         }
         if self.full_name != old.full_name {
-            diffs.push(format!(
-                "[Name {} => {}]",
-                old.full_name.as_ref().unwrap_or(&"<undefined>".into()),
-                self.full_name.as_ref().unwrap_or(&"<undefined>".into()),
+            changes.push(Change::new(
+                "user.full_name",
+                old.full_name.as_ref(),
+                self.full_name.as_ref(),
             ));
         }
         if self.email != old.email {
-            diffs.push(format!(
-                "[Email {} => {}]",
-                old.email.as_ref().unwrap_or(&"<undefined>".into()),
-                self.email.as_ref().unwrap_or(&"<undefined>".into()),
+            changes.push(Change::new(
+                "user.email",
+                old.email.as_ref(),
+                self.email.as_ref(),
+            ));
+        }
+        if self.enabled.is_some() && self.enabled != old.enabled {
+            changes.push(Change::new(
+                "user.enabled",
+                Some(old.enabled.unwrap_or(true)),
+                Some(self.enabled.unwrap_or(true)),
             ));
         }
-        if diffs.is_empty() {
-            None
-        } else {
-            Some(diffs.join(" "))
+        if self.metadata.is_some() && self.metadata != old.metadata {
+            let keys = |m: &Option<HashMap<String, serde_json::Value>>| {
+                let mut keys: Vec<&str> = m.iter().flatten().map(|(k, _)| k.as_str()).collect();
+                keys.sort();
+                keys.join(", ")
+            };
+            changes.push(Change::new(
+                "user.metadata",
+                Some(keys(&old.metadata)),
+                Some(keys(&self.metadata)),
+            ));
         }
+        changes
     }
 }
@@ -1,8 +1,280 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    elasticsearch::{base_url, Sensitive, DEFAULT_RECONNECT_AFTER, DEFAULT_TIMEOUT},
+    parse_log_level, CleanupMode,
+};
+
 pub struct Env {
+    /// Overrides the log level set on startup, e.g. on reload.
+    pub log_level: Option<log::LevelFilter>,
     pub url: String,
     pub username: String,
-    pub password: String,
+    pub password: Sensitive,
     pub skip_tls_cert_verify: bool,
+    /// Written into the generated secrets as KIBANA_URL.
+    pub kibana_url: Option<String>,
+    /// Custom CA (PEM) trusted for the connection to Elasticsearch.
+    pub ca_certificate: Option<String>,
+    /// SHA-256 of the first certificate of ca_certificate (hex).
+    pub ca_fingerprint: Option<String>,
+    /// Whether ca_certificate is written into the generated secrets.
+    pub ca_in_secret: CaInSecret,
+    /// Consecutive failures after which a CR is backed off.
+    pub failure_threshold: u32,
+    /// Requeue interval for CRs which exceeded the failure threshold.
+    pub failure_backoff: Duration,
+    /// Webhook receiving JSON notifications about failures.
+    pub notify_webhook_url: Option<String>,
+    /// HTTP sink receiving CloudEvents about user lifecycle changes.
+    pub cloudevents_sink_url: Option<String>,
+    /// Time in-flight reconciles get to finish on termination.
+    pub shutdown_grace: Duration,
+    /// Install and update the CRD on startup.
+    pub manage_crds: bool,
+    /// Reconcile ClusterElasticsearchUsers into this namespace.
+    pub cluster_users: bool,
+    /// Field manager of server-side applies to secrets and the CRD.
+    pub field_manager: String,
+    /// Finalizer set on ElasticsearchUsers.
+    pub finalizer: String,
+    /// Previous finalizer names, replaced by the current one.
+    pub legacy_finalizers: Vec<String>,
+    /// Only ElasticsearchUsers matching it are managed, e.g. for canaries.
+    pub watch_label_selector: Option<String>,
+    /// Namespaces whose ElasticsearchUsers are reconciled.
+    pub namespaces: Namespaces,
+    /// Reconciles running at once, 0 for no limit.
+    pub concurrency: usize,
+    /// Watches without events for this long are restarted, 0 disables.
+    pub watch_stall: Duration,
+    /// Sentry DSN receiving panics and unexpected errors.
+    pub sentry_dsn: Option<String>,
+    /// Elasticsearch index receiving an audit document per mutation.
+    pub audit_index: Option<String>,
+    /// Port of the HTTP server serving /healthz and /readyz.
+    pub http_port: u16,
+    /// Attempts to reach Elasticsearch at startup before giving up.
+    pub startup_retries: u32,
+    /// Secret holding ELASTIC_USERNAME and ELASTIC_PASSWORD,
+    /// watched to reload the credentials without restart.
+    pub credentials_secret: Option<String>,
+    /// Start with mutations paused.
+    pub maintenance_mode: bool,
+    /// ConfigMap whose "maintenance" key toggles the maintenance mode.
+    pub maintenance_configmap: Option<String>,
+    /// ConfigMap receiving the aggregated status report.
+    pub status_configmap: Option<String>,
+    pub status_report_interval: Duration,
+    /// ConfigMap receiving a report in each namespace receiving credentials.
+    pub namespace_status_configmap: Option<String>,
+    /// Bearer token for the HTTP API, disabled if unset.
+    pub api_token: Option<String>,
+    /// Serve the HTML dashboard on /dashboard.
+    pub dashboard: bool,
+    /// Maximum time unchanged ElasticsearchUsers skip the checks
+    /// against Elasticsearch, zero to always check.
+    pub full_sync_interval: Duration,
+    /// Default for ElasticsearchUsers without spec.cleanup.
+    pub cleanup_mode: CleanupMode,
+    /// Skip mutations while the cluster health is red.
+    pub health_gate: bool,
+    /// Log redacted request and response bodies at trace level.
+    pub trace_http: bool,
+    /// Gzip request and response bodies, e.g. across a WAN link.
+    pub compression: bool,
+    /// Connection pool of the Elasticsearch client, see ConnectionConfig.
+    pub pool_idle_timeout: Option<Duration>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub tcp_keepalive: Option<Duration>,
+    pub http2: bool,
+    pub connect_timeout: Duration,
+    /// Slow security API calls on busy clusters may need longer.
+    pub request_timeout: Duration,
+    /// Connection errors in a row before reconnecting, 0 never.
+    pub reconnect_after: u32,
+    /// Names the installation in requests to Elasticsearch, e.g.
+    /// when several installations share a cluster.
+    pub instance_id: Option<String>,
+    /// "namespace/name" of the operator pod, from the downward API.
+    pub pod: Option<String>,
+}
+
+/// Namespaces whose ElasticsearchUsers are reconciled, see
+/// WATCH_NAMESPACES, EXCLUDE_NAMESPACES and SHARDS.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Namespaces {
+    /// "*" for all, only the operator's own if empty.
+    pub watch: Vec<String>,
+    /// Never reconciled, even if watched.
+    pub exclude: Vec<String>,
+    /// Replicas sharing the namespaces, 0 or 1 without sharding.
+    pub shards: u64,
+    /// Shard of this replica, below shards.
+    pub shard: u64,
+}
+
+impl Namespaces {
+    /// Whether the ElasticsearchUsers of the namespace are reconciled.
+    pub fn enabled(&self, namespace: &str, own_namespace: &str) -> bool {
+        let watched = match self.watch.as_slice() {
+            [] => namespace == own_namespace,
+            watched => watched.iter().any(|n| n == "*" || n == namespace),
+        };
+        watched && !self.exclude.iter().any(|n| n == namespace) && self.in_shard(namespace)
+    }
+    /// Whether the namespace belongs to the shard of this replica, by a
+    /// hash of its name which is the same for all replicas and releases.
+    pub fn in_shard(&self, namespace: &str) -> bool {
+        if self.shards <= 1 {
+            return true;
+        }
+        let digest = Sha256::digest(namespace.as_bytes());
+        let hash = u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 is 32 bytes"));
+        hash % self.shards == self.shard
+    }
+    /// Name of a resource written by each replica, suffixed by the shard.
+    pub fn shard_name(&self, name: &str) -> String {
+        match self.shards > 1 {
+            true => format!("{}-{}", name, self.shard),
+            false => name.to_string(),
+        }
+    }
+    /// Names of a resource written by each replica, of all shards.
+    pub fn shard_names(&self, name: &str) -> Vec<String> {
+        match self.shards > 1 {
+            true => (0..self.shards)
+                .map(|shard| format!("{}-{}", name, shard))
+                .collect(),
+            false => vec![name.to_string()],
+        }
+    }
+    /// Whether the ElasticsearchUsers of all namespaces are reconciled
+    /// by this replica.
+    pub fn all(&self) -> bool {
+        self.watch.iter().any(|n| n == "*") && self.exclude.is_empty() && self.shards <= 1
+    }
+    /// Whether ElasticsearchUsers of other namespaces than the operator's
+    /// are reconciled, which requires cluster-wide permissions.
+    pub fn other_than(&self, own_namespace: &str) -> bool {
+        match self.watch.as_slice() {
+            [] => false,
+            [namespace] => namespace != own_namespace,
+            _ => true,
+        }
+    }
+}
+
+/// How the CA is handed to applications via the generated secrets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaInSecret {
+    None,
+    /// PEM as ELASTICSEARCH_CA_CRT
+    Certificate,
+    /// SHA-256 fingerprint as ELASTICSEARCH_CA_FINGERPRINT
+    Fingerprint,
+}
+
+/// Optional configuration file (YAML), passed via --config.
+/// Environment variables take precedence over its values.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct FileConfig {
+    log_level: Option<String>,
+    #[serde(default)]
+    elastic: ElasticConfig,
+    #[serde(default)]
+    http: HttpConfig,
+    #[serde(default)]
+    reconcile: ReconcileConfig,
+    #[serde(default)]
+    notifications: NotificationConfig,
+    #[serde(default)]
+    maintenance: MaintenanceConfig,
+    #[serde(default)]
+    status_report: StatusReportConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ElasticConfig {
+    url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    skip_verify: Option<bool>,
+    kibana_url: Option<String>,
+    ca_cert_file: Option<String>,
+    ca_in_secret: Option<String>,
+    credentials_secret: Option<String>,
+    trace_http: Option<bool>,
+    compression: Option<bool>,
+    pool_idle_timeout_seconds: Option<u64>,
+    pool_max_idle_per_host: Option<u64>,
+    tcp_keepalive_seconds: Option<u64>,
+    http2: Option<bool>,
+    connect_timeout_seconds: Option<u64>,
+    request_timeout_seconds: Option<u64>,
+    reconnect_after_failures: Option<u64>,
+    instance_id: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct HttpConfig {
+    port: Option<u64>,
+    api_token: Option<String>,
+    dashboard: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ReconcileConfig {
+    startup_retries: Option<u64>,
+    failure_threshold: Option<u64>,
+    failure_backoff_seconds: Option<u64>,
+    full_sync_interval_seconds: Option<u64>,
+    cleanup_mode: Option<String>,
+    health_gate: Option<bool>,
+    shutdown_grace_seconds: Option<u64>,
+    watch_stall_seconds: Option<u64>,
+    concurrency: Option<u64>,
+    field_manager: Option<String>,
+    manage_crds: Option<bool>,
+    cluster_users: Option<bool>,
+    shards: Option<u64>,
+    finalizer_name: Option<String>,
+    legacy_finalizers: Option<String>,
+    watch_namespaces: Option<String>,
+    watch_label_selector: Option<String>,
+    exclude_namespaces: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct NotificationConfig {
+    webhook_url: Option<String>,
+    cloud_events_sink_url: Option<String>,
+    audit_index: Option<String>,
+    sentry_dsn: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct MaintenanceConfig {
+    enabled: Option<bool>,
+    config_map: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct StatusReportConfig {
+    config_map: Option<String>,
+    interval_seconds: Option<u64>,
+    namespace_config_map: Option<String>,
 }
 
 pub fn as_bool(v: &str) -> Option<bool> {
@@ -13,20 +285,373 @@ pub fn as_bool(v: &str) -> Option<bool> {
     }
 }
 
-pub fn load_env() -> Result<Env, &'static str> {
-    let url = std::env::var("ELASTIC_URL").map_err(|_| "ELASTIC_URL undefined")?;
-    let username = std::env::var("ELASTIC_USERNAME").map_err(|_| "ELASTIC_USERNAME undefined")?;
-    let password = std::env::var("ELASTIC_PASSWORD").map_err(|_| "ELASTIC_PASSWORD undefined")?;
-    let skip_tls_cert_verify =
-        match as_bool(&std::env::var("ELASTIC_SKIP_VERIFY undefined").unwrap_or("false".into())) {
-            Some(v) => Ok(v),
-            None => Err("ELASTIC_SKIP_VERIFY must be undefined, true or false."),
-        }?;
+fn string(name: &str, file: Option<String>) -> Option<String> {
+    std::env::var(name).ok().or(file).filter(|v| !v.is_empty())
+}
+
+/// Comma separated list, e.g. "team-a, team-b".
+fn list(name: &str, file: Option<String>) -> Vec<String> {
+    string(name, file)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn required(name: &str, file: Option<String>) -> Result<String, String> {
+    string(name, file).ok_or(format!("{} undefined", name))
+}
+
+fn boolean(name: &str, file: Option<bool>, default: bool) -> Result<bool, String> {
+    match std::env::var(name) {
+        Err(_) => Ok(file.unwrap_or(default)),
+        Ok(v) => as_bool(&v).ok_or(format!("{} must be undefined, true or false.", name)),
+    }
+}
+
+/// Like as_u64, but unset without default.
+fn optional_u64(name: &str, file: Option<u64>) -> Result<Option<u64>, String> {
+    match std::env::var(name) {
+        Err(_) => Ok(file),
+        Ok(v) if v.trim().is_empty() => Ok(None),
+        Ok(v) => v
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{} must be a non-negative integer.", name)),
+    }
+}
+
+fn as_u64(name: &str, file: Option<u64>, default: u64) -> Result<u64, String> {
+    match std::env::var(name) {
+        Err(_) => Ok(file.unwrap_or(default)),
+        Ok(v) => v
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} must be a non-negative integer.", name)),
+    }
+}
+
+/// SHA-256 of the DER encoding of the first certificate in the PEM.
+fn pem_fingerprint(pem: &str) -> Option<String> {
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| *l != "-----BEGIN CERTIFICATE-----")
+        .skip(1)
+        .take_while(|l| *l != "-----END CERTIFICATE-----")
+        .collect();
+    let der = STANDARD.decode(body).ok().filter(|der| !der.is_empty())?;
+    Some(
+        Sha256::digest(der)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    )
+}
+
+/// Ordinal of a StatefulSet pod, e.g. 2 of eeops-2.
+fn pod_ordinal() -> Option<u64> {
+    let pod = string("POD_NAME", None).or_else(|| string("HOSTNAME", None))?;
+    pod.rsplit_once('-')?.1.parse().ok()
+}
+
+fn load_file(path: &str) -> Result<FileConfig, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read config file {}: {}", path, e))?;
+    serde_yaml::from_str(&content).map_err(|e| format!("Invalid config file {}: {}", path, e))
+}
+
+/// With `mock_elastic_url`, the operator talks to the mock,
+/// which accepts any admin login.
+pub fn load_env(
+    config_path: Option<&str>,
+    mock_elastic_url: Option<String>,
+) -> Result<Env, String> {
+    let file = match config_path {
+        Some(path) => load_file(path)?,
+        None => FileConfig::default(),
+    };
+    let (url, username, password) = match mock_elastic_url {
+        Some(url) => (
+            url,
+            string("ELASTIC_USERNAME", file.elastic.username).unwrap_or("elastic".into()),
+            string("ELASTIC_PASSWORD", file.elastic.password).unwrap_or("mock".into()),
+        ),
+        None => (
+            base_url(&required("ELASTIC_URL", file.elastic.url)?)?
+                .as_str()
+                .trim_end_matches('/')
+                .to_string(),
+            required("ELASTIC_USERNAME", file.elastic.username)?,
+            required("ELASTIC_PASSWORD", file.elastic.password)?,
+        ),
+    };
+    let log_level = match string("LOGLEVEL", file.log_level) {
+        Some(level) => Some(
+            parse_log_level(&level).ok_or(format!("Invalid log level {}", level.to_lowercase()))?,
+        ),
+        None => None,
+    };
+    let skip_tls_cert_verify = boolean("ELASTIC_SKIP_VERIFY", file.elastic.skip_verify, false)?;
+    let kibana_url = string("KIBANA_URL", file.elastic.kibana_url);
+    let ca_certificate = match string("ELASTIC_CA_CERT_FILE", file.elastic.ca_cert_file) {
+        Some(path) => Some(
+            std::fs::read_to_string(&path)
+                .map_err(|e| format!("Could not read CA certificate {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    let ca_fingerprint = match &ca_certificate {
+        Some(pem) => Some(
+            pem_fingerprint(pem).ok_or("ELASTIC_CA_CERT_FILE must contain a PEM certificate.")?,
+        ),
+        None => None,
+    };
+    let ca_in_secret = match string("ELASTIC_CA_IN_SECRET", file.elastic.ca_in_secret)
+        .map(|v| v.to_lowercase())
+        .as_deref()
+    {
+        None | Some("none") => CaInSecret::None,
+        Some("certificate") => CaInSecret::Certificate,
+        Some("fingerprint") => CaInSecret::Fingerprint,
+        Some(_) => {
+            return Err(
+                "ELASTIC_CA_IN_SECRET must be none, certificate or fingerprint.".to_string(),
+            )
+        }
+    };
+    if ca_in_secret != CaInSecret::None && ca_certificate.is_none() {
+        return Err("ELASTIC_CA_IN_SECRET requires ELASTIC_CA_CERT_FILE.".to_string());
+    }
+    let failure_threshold = u32::try_from(as_u64(
+        "FAILURE_THRESHOLD",
+        file.reconcile.failure_threshold,
+        5,
+    )?)
+    .map_err(|_| format!("FAILURE_THRESHOLD must be at most {}.", u32::MAX))?;
+    let failure_backoff = Duration::from_secs(as_u64(
+        "FAILURE_BACKOFF_SECONDS",
+        file.reconcile.failure_backoff_seconds,
+        3600,
+    )?);
+    let notify_webhook_url = string("NOTIFY_WEBHOOK_URL", file.notifications.webhook_url);
+    let cloudevents_sink_url = string(
+        "CLOUDEVENTS_SINK_URL",
+        file.notifications.cloud_events_sink_url,
+    );
+    let audit_index = string("AUDIT_INDEX", file.notifications.audit_index);
+    let sentry_dsn = string("SENTRY_DSN", file.notifications.sentry_dsn);
+    let http_port = as_u64("HTTP_PORT", file.http.port, 8080)?
+        .try_into()
+        .map_err(|_| "HTTP_PORT must be a valid port.")?;
+    let startup_retries = u32::try_from(as_u64(
+        "STARTUP_RETRIES",
+        file.reconcile.startup_retries,
+        10,
+    )?)
+    .map_err(|_| format!("STARTUP_RETRIES must be at most {}.", u32::MAX))?;
+    let credentials_secret = string(
+        "ELASTIC_CREDENTIALS_SECRET",
+        file.elastic.credentials_secret,
+    );
+    let maintenance_mode = boolean("MAINTENANCE_MODE", file.maintenance.enabled, false)?;
+    let maintenance_configmap = string("MAINTENANCE_CONFIGMAP", file.maintenance.config_map);
+    let status_configmap = string("STATUS_CONFIGMAP", file.status_report.config_map);
+    let namespace_status_configmap = string(
+        "NAMESPACE_STATUS_CONFIGMAP",
+        file.status_report.namespace_config_map,
+    );
+    let status_report_interval = Duration::from_secs(
+        as_u64(
+            "STATUS_REPORT_INTERVAL_SECONDS",
+            file.status_report.interval_seconds,
+            60,
+        )?
+        .max(1),
+    );
+    let api_token = string("API_TOKEN", file.http.api_token);
+    let dashboard = boolean("DASHBOARD", file.http.dashboard, false)?;
+    let cleanup_mode = match string("CLEANUP_MODE", file.reconcile.cleanup_mode)
+        .map(|v| v.to_lowercase())
+        .as_deref()
+    {
+        None | Some("delete") => CleanupMode::Delete,
+        Some("disable") => CleanupMode::Disable,
+        Some(_) => return Err("CLEANUP_MODE must be delete or disable.".to_string()),
+    };
+    let full_sync_interval = Duration::from_secs(as_u64(
+        "FULL_SYNC_INTERVAL_SECONDS",
+        file.reconcile.full_sync_interval_seconds,
+        21600,
+    )?);
+    let health_gate = boolean("HEALTH_GATE", file.reconcile.health_gate, false)?;
+    let shutdown_grace = Duration::from_secs(as_u64(
+        "SHUTDOWN_GRACE_SECONDS",
+        file.reconcile.shutdown_grace_seconds,
+        25,
+    )?);
+    let field_manager = string("FIELD_MANAGER", file.reconcile.field_manager)
+        .unwrap_or(crate::DEFAULT_FIELD_MANAGER.to_string());
+    let manage_crds = boolean("MANAGE_CRDS", file.reconcile.manage_crds, true)?;
+    let cluster_users = boolean("CLUSTER_USERS", file.reconcile.cluster_users, false)?;
+    let finalizer = string("FINALIZER_NAME", file.reconcile.finalizer_name)
+        .unwrap_or(crate::DEFAULT_FINALIZER.to_string());
+    let mut legacy_finalizers = list("LEGACY_FINALIZERS", file.reconcile.legacy_finalizers);
+    legacy_finalizers.push(crate::DEFAULT_FINALIZER.to_string());
+    legacy_finalizers.retain(|name| *name != finalizer);
+    let watch_label_selector = string("WATCH_LABEL_SELECTOR", file.reconcile.watch_label_selector);
+    let concurrency = usize::try_from(as_u64(
+        "RECONCILE_CONCURRENCY",
+        file.reconcile.concurrency,
+        16,
+    )?)
+    .map_err(|_| format!("RECONCILE_CONCURRENCY must be at most {}.", usize::MAX))?;
+    let shards = as_u64("SHARDS", file.reconcile.shards, 1)?;
+    // Differs between replicas, so not in the config file
+    let shard = match string("SHARD_INDEX", None) {
+        Some(shard) => shard
+            .trim()
+            .parse()
+            .map_err(|_| "SHARD_INDEX must be a non-negative integer.".to_string())?,
+        None if shards > 1 => pod_ordinal().ok_or(
+            "SHARD_INDEX undefined, and the pod name has no StatefulSet ordinal.".to_string(),
+        )?,
+        None => 0,
+    };
+    if shards == 0 || shard >= shards {
+        return Err("SHARDS must be positive, and SHARD_INDEX below it.".to_string());
+    }
+    let namespaces = Namespaces {
+        watch: list("WATCH_NAMESPACES", file.reconcile.watch_namespaces),
+        exclude: list("EXCLUDE_NAMESPACES", file.reconcile.exclude_namespaces),
+        shards,
+        shard,
+    };
+    let watch_stall = Duration::from_secs(as_u64(
+        "WATCH_STALL_SECONDS",
+        file.reconcile.watch_stall_seconds,
+        900,
+    )?);
+    let trace_http = boolean("ELASTIC_TRACE_HTTP", file.elastic.trace_http, false)?;
+    let compression = boolean("ELASTIC_COMPRESSION", file.elastic.compression, false)?;
+    let pool_idle_timeout = optional_u64(
+        "ELASTIC_POOL_IDLE_TIMEOUT_SECONDS",
+        file.elastic.pool_idle_timeout_seconds,
+    )?
+    .map(Duration::from_secs);
+    let pool_max_idle_per_host = optional_u64(
+        "ELASTIC_POOL_MAX_IDLE_PER_HOST",
+        file.elastic.pool_max_idle_per_host,
+    )?
+    .map(usize::try_from)
+    .transpose()
+    .map_err(|_| {
+        format!(
+            "ELASTIC_POOL_MAX_IDLE_PER_HOST must be at most {}.",
+            usize::MAX
+        )
+    })?;
+    // 0 disables, like the other durations
+    let tcp_keepalive = optional_u64(
+        "ELASTIC_TCP_KEEPALIVE_SECONDS",
+        file.elastic.tcp_keepalive_seconds,
+    )?
+    .filter(|seconds| *seconds > 0)
+    .map(Duration::from_secs);
+    let http2 = boolean("ELASTIC_HTTP2", file.elastic.http2, false)?;
+    let default_timeout = DEFAULT_TIMEOUT.as_secs();
+    let connect_timeout = Duration::from_secs(as_u64(
+        "ELASTIC_CONNECT_TIMEOUT_SECONDS",
+        file.elastic.connect_timeout_seconds,
+        default_timeout,
+    )?);
+    let request_timeout = Duration::from_secs(as_u64(
+        "ELASTIC_REQUEST_TIMEOUT_SECONDS",
+        file.elastic.request_timeout_seconds,
+        default_timeout,
+    )?);
+    let reconnect_after = as_u64(
+        "ELASTIC_RECONNECT_AFTER_FAILURES",
+        file.elastic.reconnect_after_failures,
+        DEFAULT_RECONNECT_AFTER.into(),
+    )?
+    .try_into()
+    .map_err(|_| "ELASTIC_RECONNECT_AFTER_FAILURES is too large.".to_string())?;
+    let pod_namespace = string("POD_NAMESPACE", None);
+    let instance_id =
+        string("OPERATOR_INSTANCE_ID", file.elastic.instance_id).or_else(|| pod_namespace.clone());
+    if instance_id
+        .as_deref()
+        .is_some_and(|id| !id.chars().all(|c| c.is_ascii_graphic() && c != ';'))
+    {
+        return Err("OPERATOR_INSTANCE_ID must be printable ASCII without spaces or ';'.".into());
+    }
+    // HOSTNAME is the pod name, unless overwritten in the pod spec
+    let pod = string("POD_NAME", None)
+        .or_else(|| string("HOSTNAME", None))
+        .map(|name| match &pod_namespace {
+            Some(namespace) => format!("{}/{}", namespace, name),
+            None => name,
+        });
+    if connect_timeout.is_zero() || request_timeout.is_zero() {
+        return Err(
+            "ELASTIC_CONNECT_TIMEOUT_SECONDS and ELASTIC_REQUEST_TIMEOUT_SECONDS must be positive."
+                .to_string(),
+        );
+    }
 
     Ok(Env {
+        log_level,
         url,
         username,
-        password,
+        password: password.into(),
         skip_tls_cert_verify,
+        kibana_url,
+        ca_certificate,
+        ca_fingerprint,
+        ca_in_secret,
+        failure_threshold,
+        failure_backoff,
+        notify_webhook_url,
+        cloudevents_sink_url,
+        sentry_dsn,
+        audit_index,
+        http_port,
+        startup_retries,
+        credentials_secret,
+        maintenance_mode,
+        maintenance_configmap,
+        status_configmap,
+        status_report_interval,
+        namespace_status_configmap,
+        api_token,
+        dashboard,
+        full_sync_interval,
+        cleanup_mode,
+        health_gate,
+        shutdown_grace,
+        field_manager,
+        manage_crds,
+        cluster_users,
+        finalizer,
+        legacy_finalizers,
+        watch_label_selector,
+        namespaces,
+        concurrency,
+        watch_stall,
+        trace_http,
+        compression,
+        pool_idle_timeout,
+        pool_max_idle_per_host,
+        tcp_keepalive,
+        http2,
+        connect_timeout,
+        request_timeout,
+        reconnect_after,
+        instance_id,
+        pod,
     })
 }
@@ -1,12 +1,170 @@
-use crate::elasticsearch::ElasticError;
+use std::time::Duration;
+
+use crate::{
+    elasticsearch::ElasticError, REQUEUE_CONFLICT_SECONDS, REQUEUE_MAINTENANCE_SECONDS,
+    REQUEUE_SECONDS, REQUEUE_UNAVAILABLE_SECONDS,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum OperatorError {
     #[error("{0}")]
-    ElasticError(#[from] ElasticError),
+    ElasticError(ElasticError),
     #[error("{0}")]
-    KubeError(#[from] kube::Error),
+    KubeError(kube::Error),
     #[error("[AH] {0} ({})", .0.root_cause())]
-    Anyhow(#[from] anyhow::Error),
+    Anyhow(anyhow::Error),
+    /// The CR spec can't be applied as is.
+    #[error("Invalid spec: {0}")]
+    Validation(String),
+    /// Concurrent modification, usually resolves on retry.
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Elasticsearch unavailable: {0}")]
+    ElasticsearchUnavailable(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    /// Not available on the connected cluster, e.g. on Serverless.
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+    /// Security features of Elasticsearch are disabled or unlicensed.
+    #[error("Elasticsearch security unavailable: {0}")]
+    SecurityUnavailable(String),
+    /// Secret maintained outside the operator is missing or incomplete.
+    #[error("Secret not ready: {0}")]
+    SecretNotReady(String),
+    /// Mutations are paused, see maintenance mode.
+    #[error("Paused due to maintenance mode")]
+    Maintenance,
+    /// Deletion refused, see annotation eeops.io/protected.
+    #[error("Deletion protected by annotation eeops.io/protected")]
+    Protected,
+}
+
+impl OperatorError {
+    /// Permanent errors won't go away by retrying,
+    /// only by changing the CR.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            OperatorError::Validation(_) | OperatorError::Unsupported(_)
+        )
+    }
+    /// Errors hinting at a bug or an unknown response of Elasticsearch,
+    /// as opposed to expected conditions like conflicts or outages.
+    pub fn is_unexpected(&self) -> bool {
+        matches!(
+            self,
+            OperatorError::Anyhow(_) | OperatorError::ElasticError(ElasticError::Custom(_))
+        )
+    }
+    /// Delay until the next attempt, None for permanent errors.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            OperatorError::Validation(_) | OperatorError::Unsupported(_) => None,
+            OperatorError::Conflict(_) => Some(Duration::from_secs(REQUEUE_CONFLICT_SECONDS)),
+            OperatorError::ElasticsearchUnavailable(_) => {
+                Some(Duration::from_secs(REQUEUE_UNAVAILABLE_SECONDS))
+            }
+            OperatorError::Maintenance => Some(Duration::from_secs(REQUEUE_MAINTENANCE_SECONDS)),
+            _ => Some(Duration::from_secs(REQUEUE_SECONDS)),
+        }
+    }
+    /// Machine readable classification, see ErrorCode.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            OperatorError::ElasticError(_) => ErrorCode::ElasticError,
+            OperatorError::KubeError(_) => ErrorCode::KubernetesError,
+            OperatorError::Anyhow(_) => ErrorCode::Unexpected,
+            OperatorError::Validation(_) => ErrorCode::InvalidSpec,
+            OperatorError::Conflict(_) => ErrorCode::Conflict,
+            OperatorError::ElasticsearchUnavailable(_) => ErrorCode::ElasticUnreachable,
+            OperatorError::Forbidden(_) => ErrorCode::Forbidden,
+            OperatorError::Unsupported(_) => ErrorCode::Unsupported,
+            OperatorError::SecurityUnavailable(_) => ErrorCode::SecurityUnavailable,
+            OperatorError::SecretNotReady(_) => ErrorCode::SecretNotReady,
+            OperatorError::Maintenance => ErrorCode::Maintenance,
+            OperatorError::Protected => ErrorCode::Protected,
+        }
+    }
+}
+
+/// Stable error codes exposed in the status, so automation
+/// does not need to parse error messages.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum ErrorCode {
+    InvalidSpec,
+    Conflict,
+    ElasticUnreachable,
+    Forbidden,
+    Unsupported,
+    SecurityUnavailable,
+    SecretNotReady,
+    Maintenance,
+    Protected,
+    ElasticError,
+    KubernetesError,
+    Unexpected,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidSpec => "InvalidSpec",
+            ErrorCode::Conflict => "Conflict",
+            ErrorCode::ElasticUnreachable => "ElasticUnreachable",
+            ErrorCode::Forbidden => "Forbidden",
+            ErrorCode::Unsupported => "Unsupported",
+            ErrorCode::SecurityUnavailable => "SecurityUnavailable",
+            ErrorCode::SecretNotReady => "SecretNotReady",
+            ErrorCode::Maintenance => "Maintenance",
+            ErrorCode::Protected => "Protected",
+            ErrorCode::ElasticError => "ElasticError",
+            ErrorCode::KubernetesError => "KubernetesError",
+            ErrorCode::Unexpected => "Unexpected",
+        }
+    }
+}
+
+impl From<ElasticError> for OperatorError {
+    fn from(value: ElasticError) -> Self {
+        match value {
+            ElasticError::HttpRequest(e) if e.is_connect() || e.is_timeout() => {
+                OperatorError::ElasticsearchUnavailable(e.to_string())
+            }
+            e @ (ElasticError::WrongCredentials
+            | ElasticError::NotSuperuser
+            | ElasticError::Forbidden(_)) => OperatorError::Forbidden(e.to_string()),
+            ElasticError::VersionConflict(message) => OperatorError::Conflict(message),
+            ElasticError::SecurityDisabled(message) => OperatorError::SecurityUnavailable(message),
+            e => OperatorError::ElasticError(e),
+        }
+    }
+}
+
+impl From<kube::Error> for OperatorError {
+    fn from(value: kube::Error) -> Self {
+        match value {
+            kube::Error::Api(ae) if ae.code == 409 => OperatorError::Conflict(ae.message),
+            kube::Error::Api(ae) if ae.code == 403 => OperatorError::Forbidden(ae.message),
+            e => OperatorError::KubeError(e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for OperatorError {
+    fn from(value: anyhow::Error) -> Self {
+        // ElasticAdmin mostly returns anyhow errors,
+        // recover the underlying error for classification.
+        let value = match value.downcast::<ElasticError>() {
+            Ok(e) => return e.into(),
+            Err(value) => value,
+        };
+        match value.downcast::<reqwest::Error>() {
+            Ok(e) => ElasticError::HttpRequest(e).into(),
+            Err(value) => OperatorError::Anyhow(value),
+        }
+    }
 }
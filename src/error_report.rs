@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use kube::ResourceExt;
+use log::{debug, warn};
+use reqwest::Url;
+use serde_json::json;
+
+use crate::{correlation, error::OperatorError, ElasticsearchUser};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Sends panics and unexpected errors to Sentry, if a DSN is configured.
+pub struct ErrorReporter {
+    target: Option<SentryTarget>,
+    client: reqwest::Client,
+    cluster: String,
+    /// Set once connected, part of every report.
+    elasticsearch_version: OnceLock<String>,
+}
+
+/// Envelope endpoint and public key, parsed from the DSN
+/// `https://<key>@<host>/<project id>`.
+struct SentryTarget {
+    envelope_url: String,
+    dsn: String,
+    public_key: String,
+}
+
+impl SentryTarget {
+    fn parse(dsn: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid Sentry DSN {}", dsn);
+        let url = Url::parse(dsn).map_err(|_| invalid())?;
+        let public_key = url.username().to_string();
+        let path = url.path().trim_end_matches('/');
+        let (prefix, project) = path.rsplit_once('/').ok_or_else(invalid)?;
+        if public_key.is_empty() || project.is_empty() {
+            return Err(invalid());
+        }
+        let mut base = url.clone();
+        base.set_username("").map_err(|_| invalid())?;
+        base.set_path("");
+        Ok(Self {
+            envelope_url: format!(
+                "{}{}/api/{}/envelope/",
+                base.as_str().trim_end_matches('/'),
+                prefix,
+                project
+            ),
+            dsn: dsn.to_string(),
+            public_key,
+        })
+    }
+}
+
+impl ErrorReporter {
+    pub fn new(dsn: Option<&str>, cluster: impl ToString) -> Result<Self, String> {
+        Ok(Self {
+            target: dsn.map(SentryTarget::parse).transpose()?,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_millis(5_000))
+                .build()
+                .expect("Unexpected error in building HTTP Client"),
+            cluster: cluster.to_string(),
+            elasticsearch_version: OnceLock::new(),
+        })
+    }
+    pub fn set_elasticsearch_version(&self, version: String) {
+        let _ = self.elasticsearch_version.set(version);
+    }
+    /// Report an error of a reconcile in the background, if it is not one
+    /// of the expected ones like conflicts or an unreachable Elasticsearch.
+    pub(crate) fn reconcile_failed(
+        self: &Arc<Self>,
+        user: &ElasticsearchUser,
+        error: &OperatorError,
+    ) {
+        if self.target.is_none() || !error.is_unexpected() {
+            return;
+        }
+        let tags = BTreeMap::from([
+            ("namespace", user.namespace().unwrap_or_default()),
+            ("name", user.name_any()),
+            ("error_code", error.code().as_str().to_string()),
+        ]);
+        let event = self.event(&error.to_string(), tags);
+        let reporter = self.clone();
+        tokio::spawn(async move { reporter.send(event).await });
+    }
+    fn event(&self, message: &str, mut tags: BTreeMap<&str, String>) -> serde_json::Value {
+        tags.insert("cluster", self.cluster.clone());
+        if let Some(version) = self.elasticsearch_version.get() {
+            tags.insert("elasticsearch_version", version.clone());
+        }
+        if let Some(id) = correlation::current() {
+            tags.insert("correlation_id", id);
+        }
+        json!({
+            "event_id": format!("{:032x}", rand::random::<u128>()),
+            "timestamp": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            "platform": "other",
+            "level": "error",
+            "logger": "ext_elasticsearch_operator",
+            "release": format!("ext-elasticsearch-operator@{}", VERSION),
+            "message": { "formatted": message },
+            "tags": tags,
+        })
+    }
+    async fn send(&self, event: serde_json::Value) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        let body = format!(
+            "{}\n{}\n{}\n",
+            json!({ "event_id": event["event_id"], "dsn": target.dsn }),
+            json!({ "type": "event" }),
+            event
+        );
+        let auth = format!(
+            "Sentry sentry_version=7, sentry_key={}, sentry_client=ext-elasticsearch-operator/{}",
+            target.public_key, VERSION
+        );
+        let res = self
+            .client
+            .post(&target.envelope_url)
+            .header("X-Sentry-Auth", auth)
+            .header("Content-Type", "application/x-sentry-envelope")
+            .body(body)
+            .send()
+            .await;
+        match res {
+            Ok(res) if res.status().is_success() => debug!("Reported error to Sentry"),
+            Ok(res) => warn!("Sentry responded with {}", res.status()),
+            Err(e) => warn!("Could not report error to Sentry: {}", e),
+        }
+    }
+}
+
+/// Report panics before the default hook prints them. The report is
+/// sent from its own thread and runtime, as the panicking one may be
+/// about to exit.
+pub fn install_panic_hook(reporter: Arc<ErrorReporter>) {
+    if reporter.target.is_none() {
+        return;
+    }
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let location = info
+            .location()
+            .map(|l| format!(" at {}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        let event = reporter.event(&format!("Panic{}: {}", location, payload), BTreeMap::new());
+        let reporter = reporter.clone();
+        let _ = std::thread::spawn(move || {
+            match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime.block_on(reporter.send(event)),
+                Err(e) => warn!("Could not report panic to Sentry: {}", e),
+            }
+        })
+        .join();
+        default_hook(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dsn_parsed() {
+        let target = SentryTarget::parse("https://abc@o1.ingest.sentry.io/42").unwrap();
+        assert_eq!(
+            target.envelope_url,
+            "https://o1.ingest.sentry.io/api/42/envelope/"
+        );
+        assert_eq!(target.public_key, "abc");
+        let target = SentryTarget::parse("http://abc@sentry:9000/path/7").unwrap();
+        assert_eq!(
+            target.envelope_url,
+            "http://sentry:9000/path/api/7/envelope/"
+        );
+        assert!(SentryTarget::parse("https://o1.ingest.sentry.io/42").is_err());
+    }
+}
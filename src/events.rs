@@ -0,0 +1,35 @@
+use kube::{
+    runtime::events::{Event, EventType, Recorder, Reporter},
+    Client, Resource,
+};
+use log::warn;
+
+use crate::ElasticsearchUser;
+
+const REPORTER: &str = "ext-elasticsearch-operator";
+
+/// Publish a Kubernetes Event on the CR. Failing to do so
+/// is only logged, as events are informational.
+pub async fn publish(
+    client: &Client,
+    user: &ElasticsearchUser,
+    type_: EventType,
+    reason: &str,
+    note: String,
+) {
+    let reporter = Reporter {
+        controller: REPORTER.into(),
+        instance: None,
+    };
+    let recorder = Recorder::new(client.clone(), reporter, user.object_ref(&()));
+    let event = Event {
+        type_,
+        reason: reason.into(),
+        note: Some(note),
+        action: "Reconcile".into(),
+        secondary: None,
+    };
+    if let Err(e) = recorder.publish(event).await {
+        warn!("Could not publish event {}: {}", reason, e);
+    }
+}
@@ -0,0 +1,34 @@
+use serde_json::{json, Value};
+
+use crate::ElasticsearchUser;
+
+/// Apply `patch` like a JSON merge patch, null removes a key.
+fn merge(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match value {
+                    Value::Null => {
+                        target.remove(&key);
+                    }
+                    value => merge(target.entry(key).or_insert(Value::Null), value),
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+/// ElasticsearchUser `name` in namespace default, reading x-* with
+/// username and secretRef `name`. `overrides` are merged into it,
+/// e.g. `{"spec": {"permissions": null}, "status": {"ok": true}}`.
+pub fn user(name: &str, overrides: Value) -> ElasticsearchUser {
+    let mut user = json!({
+        "apiVersion": "eeops.io/v1",
+        "kind": "ElasticsearchUser",
+        "metadata": {"name": name, "namespace": "default"},
+        "spec": {"secretRef": name, "username": name, "prefixes": ["x-"], "permissions": "Read"},
+    });
+    merge(&mut user, overrides);
+    serde_json::from_value(user).expect("Invalid ElasticsearchUser fixture")
+}
@@ -0,0 +1,394 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use kube::{
+    api::{DeleteParams, ListParams, Patch, PatchParams},
+    runtime::{controller::Action, watcher, Controller},
+    Api, Client, Resource, ResourceExt,
+};
+use kube_derive::CustomResource;
+use log::{debug, info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::OperatorError, field_manager, reconciliation::ROLE_PREFIX, ElasticsearchUser,
+    ElasticsearchUserSpec, GlobalPrivilegesSpec, IndexManagementSpec, SecretFormat,
+    UserPermissions, REQUEUE_SECONDS,
+};
+
+/// Set on the ElasticsearchUsers of a group to its name.
+pub const GROUP_LABEL: &str = "eeops.io/group";
+/// Upper bound of spec.count, against typos creating thousands of users.
+const MAX_MEMBERS: u32 = 1000;
+
+/// Many Elasticsearch users sharing one access profile, e.g. one
+/// credential per replica or environment. An ElasticsearchUser is
+/// created per member, all granted the same role.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "eeops.io",
+    version = "v1",
+    kind = "ElasticsearchUserGroup",
+    namespaced,
+    shortname = "esusergroup",
+    shortname = "esug",
+    category = "elastic"
+)]
+#[kube(status = "ElasticsearchUserGroupStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct ElasticsearchUserGroupSpec {
+    /// Usernames of the members, unless generated by count.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    usernames: Vec<String>,
+    /// Generate this many members, <usernamePrefix>-0 to -<count - 1>.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    count: Option<u32>,
+    /// Prefix of the generated usernames, defaults to the group name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username_prefix: Option<String>,
+    /// Secret of each member, may contain {{ .username }}. Defaults to
+    /// the name of the member's ElasticsearchUser, <group>-<username>.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_ref: Option<String>,
+    // Same as in ElasticsearchUser, passed on to the members
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    class_ref: Option<String>,
+    prefixes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<UserPermissions>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    privileges_raw: Vec<String>,
+    #[serde(default)]
+    monitor: bool,
+    #[serde(default)]
+    view_index_metadata: bool,
+    #[serde(default)]
+    maintenance: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    index_management: Vec<IndexManagementSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    global: Option<GlobalPrivilegesSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_format: Option<SecretFormat>,
+    #[serde(default)]
+    basic_auth_header: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ElasticsearchUserGroupStatus {
+    /// All members exist and are ready.
+    pub ok: bool,
+    pub error_message: Option<String>,
+    /// Usernames of the members.
+    #[serde(default)]
+    pub members: Vec<String>,
+    /// Members whose ElasticsearchUser reconciled successfully.
+    #[serde(default)]
+    pub ready_members: u32,
+    /// Role shared by the members.
+    pub role: Option<String>,
+}
+
+/// Member of a group and the name of its ElasticsearchUser.
+#[derive(Debug, PartialEq, Eq)]
+struct Member {
+    name: String,
+    username: String,
+}
+
+/// Role shared by the members, distinct from those of single users.
+pub fn group_role_name(group: &ElasticsearchUserGroup) -> String {
+    format!(
+        "{}group-{}-{}",
+        ROLE_PREFIX,
+        group.namespace().unwrap_or_default(),
+        group.name_any()
+    )
+}
+
+/// ElasticsearchUser name of a member, e.g. "ingest-bob-smith" for
+/// "Bob Smith" in group "ingest".
+fn member_name(group: &str, username: &str) -> String {
+    let suffix: String = username
+        .to_lowercase()
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect();
+    format!("{}-{}", group, suffix.trim_matches(['-', '.']))
+}
+
+fn members(group: &ElasticsearchUserGroup) -> Result<Vec<Member>, OperatorError> {
+    let spec = &group.spec;
+    let usernames: Vec<String> = match (spec.usernames.is_empty(), spec.count) {
+        (false, None) => spec.usernames.clone(),
+        (true, Some(count)) if count <= MAX_MEMBERS => {
+            let prefix = spec.username_prefix.clone().unwrap_or(group.name_any());
+            (0..count).map(|i| format!("{}-{}", prefix, i)).collect()
+        }
+        (true, Some(_)) => {
+            return Err(OperatorError::Validation(format!(
+                "count must not exceed {}",
+                MAX_MEMBERS
+            )))
+        }
+        _ => {
+            return Err(OperatorError::Validation(
+                "exactly one of usernames and count is required".to_string(),
+            ))
+        }
+    };
+    let mut members: Vec<Member> = Vec::new();
+    for username in usernames {
+        let name = member_name(&group.name_any(), &username);
+        if let Some(other) = members.iter().find(|m| m.name == name) {
+            return Err(OperatorError::Validation(format!(
+                "usernames {} and {} map to the same ElasticsearchUser {}",
+                other.username, username, name
+            )));
+        }
+        members.push(Member { name, username });
+    }
+    if let Some(secret_ref) = &spec.secret_ref {
+        if members.len() > 1 && !per_member(secret_ref) {
+            return Err(OperatorError::Validation(format!(
+                "secretRef \"{}\" must contain {{{{ .username }}}} or {{{{ .metadata.name }}}}, \
+                 as the members would share the secret",
+                secret_ref
+            )));
+        }
+    }
+    Ok(members)
+}
+
+/// Whether secretRef differs between members, by a variable of the member.
+fn per_member(secret_ref: &str) -> bool {
+    secret_ref.split("{{").skip(1).any(|part| {
+        let variable = part.split("}}").next().unwrap_or_default().trim();
+        variable == ".username" || variable == ".metadata.name"
+    })
+}
+
+/// ElasticsearchUser of a member, owned by the group.
+fn member_user(
+    group: &ElasticsearchUserGroup,
+    member: &Member,
+) -> Result<ElasticsearchUser, OperatorError> {
+    // The shared fields have the same names in both specs
+    let mut spec = serde_json::to_value(&group.spec).expect("Serde JSON failed to serialize spec");
+    spec["username"] = member.username.clone().into();
+    spec["secretRef"] = group
+        .spec
+        .secret_ref
+        .clone()
+        .unwrap_or(member.name.clone())
+        .into();
+    spec["roleName"] = group_role_name(group).into();
+    let spec: ElasticsearchUserSpec = serde_json::from_value(spec)
+        .map_err(|e| OperatorError::Validation(format!("invalid member spec: {}", e)))?;
+    let mut user = ElasticsearchUser::new(&member.name, spec);
+    let mut labels: BTreeMap<String, String> = group.labels().clone();
+    labels.insert(GROUP_LABEL.to_string(), group.name_any());
+    user.metadata.labels = Some(labels);
+    user.metadata.owner_references = group.controller_owner_ref(&()).map(|o| vec![o]);
+    Ok(user)
+}
+
+/// Apply the ElasticsearchUsers of the members and delete
+/// those of removed members.
+async fn apply_members(
+    group: &ElasticsearchUserGroup,
+    users: &Api<ElasticsearchUser>,
+) -> Result<ElasticsearchUserGroupStatus, OperatorError> {
+    let members = members(group)?;
+    let params = PatchParams::apply(field_manager()).force();
+    for member in &members {
+        let user = member_user(group, member)?;
+        users
+            .patch(&member.name, &params, &Patch::Apply(&user))
+            .await?;
+    }
+    let selector = format!("{}={}", GROUP_LABEL, group.name_any());
+    let uid = group.uid();
+    let mut ready_members = 0;
+    for user in users.list(&ListParams::default().labels(&selector)).await? {
+        let owned = user
+            .owner_references()
+            .iter()
+            .any(|o| Some(&o.uid) == uid.as_ref());
+        if !owned {
+            continue;
+        }
+        if !members.iter().any(|m| m.name == user.name_any()) {
+            info!(
+                "Delete ElasticsearchUser {}, removed from group {}",
+                user.name_any(),
+                group.name_any()
+            );
+            users
+                .delete(&user.name_any(), &DeleteParams::default())
+                .await?;
+        } else if user.status.as_ref().is_some_and(|s| s.ok) {
+            ready_members += 1;
+        }
+    }
+    Ok(ElasticsearchUserGroupStatus {
+        ok: ready_members as usize == members.len(),
+        error_message: None,
+        members: members.into_iter().map(|m| m.username).collect(),
+        ready_members,
+        role: Some(group_role_name(group)),
+    })
+}
+
+async fn reconcile(
+    group: Arc<ElasticsearchUserGroup>,
+    client: Arc<Client>,
+) -> Result<Action, OperatorError> {
+    debug!("Reconcile ElasticsearchUserGroup {}", group.name_any());
+    let users: Api<ElasticsearchUser> = Api::default_namespaced((*client).clone());
+    let groups: Api<ElasticsearchUserGroup> = Api::default_namespaced((*client).clone());
+    let result = apply_members(&group, &users).await;
+    let status = match &result {
+        Ok(status) => status.clone(),
+        Err(e) => ElasticsearchUserGroupStatus {
+            error_message: Some(e.to_string()),
+            ..group.status.clone().unwrap_or_default()
+        },
+    };
+    if group.status.as_ref() != Some(&status) {
+        let patch = Patch::Merge(serde_json::json!({ "status": status }));
+        groups
+            .patch_status(&group.name_any(), &PatchParams::default(), &patch)
+            .await?;
+    }
+    result.map(|_| Action::requeue(Duration::from_secs(REQUEUE_SECONDS)))
+}
+
+fn error_policy(
+    group: Arc<ElasticsearchUserGroup>,
+    error: &OperatorError,
+    _client: Arc<Client>,
+) -> Action {
+    warn!(
+        "Reconcile ElasticsearchUserGroup {} failed: {}",
+        group.name_any(),
+        error
+    );
+    Action::requeue(
+        error
+            .retry_after()
+            .unwrap_or(Duration::from_secs(REQUEUE_SECONDS)),
+    )
+}
+
+/// Reconcile the ElasticsearchUserGroups, also whenever the
+/// ElasticsearchUser of a member changes, e.g. its readiness.
+pub async fn run(client: Client, label_selector: Option<String>) {
+    let groups: Api<ElasticsearchUserGroup> = Api::default_namespaced(client.clone());
+    let users: Api<ElasticsearchUser> = Api::default_namespaced(client.clone());
+    let config = match &label_selector {
+        Some(selector) => watcher::Config::default().labels(selector),
+        None => watcher::Config::default(),
+    };
+    Controller::new(groups, config)
+        .owns(users, watcher::Config::default().labels(GROUP_LABEL))
+        .run(reconcile, error_policy, Arc::new(client))
+        .for_each(|res| async move {
+            if let Err(e) = res {
+                debug!("Reconcile ElasticsearchUserGroup failed: {:?}", e);
+            }
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(spec: serde_json::Value) -> ElasticsearchUserGroup {
+        let mut group: ElasticsearchUserGroup = serde_json::from_value(serde_json::json!({
+            "apiVersion": "eeops.io/v1",
+            "kind": "ElasticsearchUserGroup",
+            "metadata": {"name": "ingest", "namespace": "default", "uid": "uid-g"},
+            "spec": spec,
+        }))
+        .unwrap();
+        group.metadata.labels = Some([("team".to_string(), "a".to_string())].into());
+        group
+    }
+
+    #[test]
+    fn members_share_role_and_profile() {
+        let group = group(serde_json::json!({
+            "count": 2,
+            "prefixes": ["logs-"],
+            "permissions": "Write",
+            "monitor": true,
+        }));
+        let members = members(&group).unwrap();
+        assert_eq!(
+            members,
+            vec![
+                Member {
+                    name: "ingest-ingest-0".into(),
+                    username: "ingest-0".into()
+                },
+                Member {
+                    name: "ingest-ingest-1".into(),
+                    username: "ingest-1".into()
+                },
+            ]
+        );
+        let user = member_user(&group, &members[1]).unwrap();
+        assert_eq!(user.spec.username, "ingest-1");
+        assert_eq!(user.spec.secret_ref, "ingest-ingest-1");
+        assert_eq!(
+            user.spec.role_name.as_deref(),
+            Some("role-group-default-ingest")
+        );
+        assert!(user.spec.monitor);
+        assert_eq!(user.spec.prefixes, vec!["logs-"]);
+        assert_eq!(user.labels()[GROUP_LABEL], "ingest");
+        assert_eq!(user.labels()["team"], "a");
+        assert_eq!(user.owner_references()[0].uid, "uid-g");
+    }
+
+    #[test]
+    fn member_names_validated() {
+        let both = group(serde_json::json!({
+            "usernames": ["a"], "count": 1, "prefixes": ["x"], "permissions": "Read"
+        }));
+        assert!(members(&both).is_err());
+        let clash = group(serde_json::json!({
+            "usernames": ["Bob", "bob"], "prefixes": ["x"], "permissions": "Read"
+        }));
+        assert!(members(&clash).is_err());
+        assert_eq!(member_name("ingest", "Bob Smith"), "ingest-bob-smith");
+    }
+
+    #[test]
+    fn shared_secret_ref_rejected() {
+        let shared = group(serde_json::json!({
+            "count": 2, "secretRef": "ingest-es", "prefixes": ["x"], "permissions": "Read"
+        }));
+        assert!(matches!(
+            members(&shared),
+            Err(OperatorError::Validation(_))
+        ));
+        let single = group(serde_json::json!({
+            "count": 1, "secretRef": "ingest-es", "prefixes": ["x"], "permissions": "Read"
+        }));
+        assert!(members(&single).is_ok());
+        let templated = group(serde_json::json!({
+            "count": 2, "secretRef": "{{.username}}-es", "prefixes": ["x"], "permissions": "Read"
+        }));
+        assert!(members(&templated).is_ok());
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use kube::{runtime::reflector::Store, ResourceExt};
+use log::{error, info};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    dashboard, error::ErrorCode, liveness::WatchHealth, metrics::Metrics, parse_log_level,
+    ElasticsearchUser, UserPermissions,
+};
+
+/// State shared between the operator and the HTTP server.
+pub struct HttpState {
+    ready: AtomicBool,
+    /// Bearer token required for /api, which is disabled without.
+    api_token: Option<String>,
+    /// Whether /dashboard is served, see DASHBOARD.
+    dashboard: bool,
+    /// Cache of the controller, available once it is started.
+    users: OnceLock<Store<ElasticsearchUser>>,
+    metrics: Arc<Metrics>,
+    /// Watches of the controller, failing /healthz once dead.
+    watches: Mutex<Vec<Arc<WatchHealth>>>,
+}
+
+/// Managed user as listed by GET /api/v1/users.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UserSummary<'a> {
+    namespace: String,
+    name: String,
+    username: &'a str,
+    secret_ref: &'a str,
+    prefixes: &'a [String],
+    permissions: Option<UserPermissions>,
+    ok: Option<bool>,
+    error_code: Option<ErrorCode>,
+    error_message: Option<&'a str>,
+    last_sync_time: Option<&'a str>,
+    last_successful_sync: Option<&'a str>,
+}
+
+impl<'a> UserSummary<'a> {
+    fn new(user: &'a ElasticsearchUser) -> Self {
+        let status = user.status.as_ref();
+        Self {
+            namespace: user.namespace().unwrap_or_default(),
+            name: user.name_any(),
+            username: user.username(),
+            secret_ref: &user.spec.secret_ref,
+            prefixes: &user.spec.prefixes,
+            permissions: user.spec.permissions,
+            ok: status.map(|s| s.ok),
+            error_code: status.and_then(|s| s.error_code),
+            error_message: status.and_then(|s| s.error_message.as_deref()),
+            last_sync_time: status.and_then(|s| s.last_sync_time.as_deref()),
+            last_successful_sync: status.and_then(|s| s.last_successful_sync.as_deref()),
+        }
+    }
+}
+
+impl HttpState {
+    pub fn new(api_token: Option<String>, dashboard: bool, metrics: Arc<Metrics>) -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            api_token,
+            dashboard,
+            users: OnceLock::new(),
+            metrics,
+            watches: Mutex::new(Vec::new()),
+        }
+    }
+    pub(crate) fn set_users(&self, store: Store<ElasticsearchUser>) {
+        let _ = self.users.set(store);
+    }
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Relaxed);
+    }
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+    pub(crate) fn add_watch(&self, health: Arc<WatchHealth>) {
+        self.watches
+            .lock()
+            .expect("Watches lock poisoned")
+            .push(health);
+    }
+    fn liveness_problem(&self) -> Option<String> {
+        let watches = self.watches.lock().expect("Watches lock poisoned");
+        watches.iter().find_map(|w| w.problem())
+    }
+}
+
+pub(crate) fn text(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(body.into())
+        .expect("Unexpected error in building HTTP response")
+}
+
+/// Change the log level at runtime, body is e.g. "trace".
+async fn put_log_level(req: Request<Body>) -> Response<Body> {
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return text(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let requested = String::from_utf8_lossy(&body).to_string();
+    match parse_log_level(&requested) {
+        Some(level) => {
+            log::set_max_level(level);
+            info!("Loglevel changed to {}.", level);
+            text(StatusCode::OK, level.to_string())
+        }
+        None => text(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Loglevel \"{}\" unknown [trace, debug, info, warn, error]",
+                requested.trim()
+            ),
+        ),
+    }
+}
+
+pub(crate) fn json(status: StatusCode, body: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(
+            serde_json::to_vec(body)
+                .expect("Serde JSON failed to serialize response")
+                .into(),
+        )
+        .expect("Unexpected error in building HTTP response")
+}
+
+fn is_authorized(req: &Request<Body>, state: &HttpState) -> bool {
+    let Some(token) = &state.api_token else {
+        return false;
+    };
+    let Some(given) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Compare digests of equal length without early exit,
+    // so the time taken does not reveal the token
+    let (given, token) = (Sha256::digest(given), Sha256::digest(token));
+    given
+        .iter()
+        .zip(token.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// The API always needs the token, the dashboard and changing
+/// the log level only if API_TOKEN is set.
+fn needs_token(req: &Request<Body>, state: &HttpState) -> bool {
+    let protected = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/dashboard") | (&Method::PUT, "/loglevel") => true,
+        (_, path) => return path.starts_with("/api/"),
+    };
+    protected && state.api_token.is_some()
+}
+
+fn list_users(state: &HttpState) -> Response<Body> {
+    let Some(store) = state.users.get() else {
+        return text(StatusCode::SERVICE_UNAVAILABLE, "not ready");
+    };
+    let users = store.state();
+    let summaries: Vec<UserSummary> = users.iter().map(|u| UserSummary::new(u)).collect();
+    json(StatusCode::OK, &summaries)
+}
+
+fn dashboard(state: &HttpState) -> Response<Body> {
+    let Some(store) = state.users.get() else {
+        return text(StatusCode::SERVICE_UNAVAILABLE, "not ready");
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(dashboard::render(&store.state()).into())
+        .expect("Unexpected error in building HTTP response")
+}
+
+fn metrics(state: &HttpState) -> Response<Body> {
+    let users = state.users.get().map(Store::state).unwrap_or_default();
+    let body = state.metrics.render(users.iter().map(AsRef::as_ref));
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(body.into())
+        .expect("Unexpected error in building HTTP response")
+}
+
+async fn handle(req: Request<Body>, state: Arc<HttpState>) -> Result<Response<Body>, Infallible> {
+    if needs_token(&req, &state) && !is_authorized(&req, &state) {
+        return Ok(text(StatusCode::UNAUTHORIZED, "unauthorized"));
+    }
+    let res = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/api/v1/users") => list_users(&state),
+        (&Method::GET, "/loglevel") => text(StatusCode::OK, log::max_level().to_string()),
+        (&Method::PUT, "/loglevel") => put_log_level(req).await,
+        (&Method::GET, "/metrics") => metrics(&state),
+        (&Method::GET, "/dashboard") if state.dashboard => dashboard(&state),
+        (&Method::GET, "/healthz") => match state.liveness_problem() {
+            Some(problem) => {
+                error!("Liveness probe failing: {}", problem);
+                text(StatusCode::SERVICE_UNAVAILABLE, problem)
+            }
+            None => text(StatusCode::OK, "ok"),
+        },
+        (&Method::GET, "/readyz") if state.is_ready() => text(StatusCode::OK, "ready"),
+        (&Method::GET, "/readyz") => text(StatusCode::SERVICE_UNAVAILABLE, "not ready"),
+        _ => text(StatusCode::NOT_FOUND, "not found"),
+    };
+    Ok(res)
+}
+
+/// Serve the probe and metrics endpoints until the process exits.
+pub async fn serve(port: u16, state: Arc<HttpState>) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, state.clone()))) }
+    });
+    let server = match Server::try_bind(&addr) {
+        Ok(builder) => builder.serve(make_service),
+        Err(e) => {
+            error!("Could not bind HTTP server to {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("HTTP server listening on {}", addr);
+    if let Err(e) = server.await {
+        error!("HTTP server stopped: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn status(state: &Arc<HttpState>, path: &str, token: Option<&str>) -> StatusCode {
+        let mut req = Request::get(path);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let req = req.body(Body::empty()).expect("Invalid request");
+        handle(req, state.clone()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn dashboard_needs_token_if_set() {
+        let open = Arc::new(HttpState::new(None, true, Arc::new(Metrics::new())));
+        assert_ne!(
+            status(&open, "/dashboard", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        let state = Arc::new(HttpState::new(
+            Some("secret".to_string()),
+            true,
+            Arc::new(Metrics::new()),
+        ));
+        let unauthorized = StatusCode::UNAUTHORIZED;
+        assert_eq!(status(&state, "/dashboard", None).await, unauthorized);
+        assert_eq!(
+            status(&state, "/dashboard", Some("wrong")).await,
+            unauthorized
+        );
+        assert_eq!(
+            status(&state, "/dashboard", Some("secrets")).await,
+            unauthorized
+        );
+        assert_ne!(
+            status(&state, "/dashboard", Some("secret")).await,
+            unauthorized
+        );
+    }
+
+    #[tokio::test]
+    async fn log_level_change_needs_token_if_set() {
+        let state = Arc::new(HttpState::new(
+            Some("secret".to_string()),
+            false,
+            Arc::new(Metrics::new()),
+        ));
+        let put = |token: Option<&str>| {
+            let mut req = Request::put("/loglevel");
+            if let Some(token) = token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            handle(req.body("unknown".into()).unwrap(), state.clone())
+        };
+        assert_eq!(put(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            put(Some("secret")).await.unwrap().status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(status(&state, "/loglevel", None).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+use kube::runtime::watcher;
+use log::warn;
+
+/// Health of a watch, reported by /healthz. A watch is unhealthy if it
+/// keeps failing, e.g. after the operator's RBAC permissions expired, or
+/// does not deliver events even after being restarted.
+pub struct WatchHealth {
+    name: &'static str,
+    /// Zero disables restarts and the health check.
+    stall: Duration,
+    state: Mutex<WatchState>,
+}
+
+struct WatchState {
+    last_event: Instant,
+    failing_since: Option<Instant>,
+}
+
+impl WatchHealth {
+    pub fn new(name: &'static str, stall: Duration) -> Self {
+        Self {
+            name,
+            stall,
+            state: Mutex::new(WatchState {
+                last_event: Instant::now(),
+                failing_since: None,
+            }),
+        }
+    }
+    fn observe<T>(&self, result: &watcher::Result<T>) {
+        let mut state = self.state.lock().expect("Watch health lock poisoned");
+        match result {
+            Ok(_) => {
+                state.last_event = Instant::now();
+                state.failing_since = None;
+            }
+            Err(_) => {
+                state.failing_since.get_or_insert_with(Instant::now);
+            }
+        }
+    }
+    /// Reason the watch is considered dead, None while healthy.
+    pub fn problem(&self) -> Option<String> {
+        if self.stall.is_zero() {
+            return None;
+        }
+        let state = self.state.lock().expect("Watch health lock poisoned");
+        if let Some(since) = state.failing_since.filter(|s| s.elapsed() > self.stall) {
+            return Some(format!(
+                "watch of {} failing for {}s",
+                self.name,
+                since.elapsed().as_secs()
+            ));
+        }
+        let idle = state.last_event.elapsed();
+        // Restarted after one stall period, so a working watch
+        // delivers events again within the second.
+        (idle > self.stall * 2).then(|| {
+            format!(
+                "no events from the watch of {} for {}s",
+                self.name,
+                idle.as_secs()
+            )
+        })
+    }
+}
+
+type EventStream<K> = BoxStream<'static, watcher::Result<watcher::Event<K>>>;
+
+/// Watch stream, which is started again once it did not deliver any
+/// event for the stall period. The restart lists all objects, so an idle
+/// watch delivers events right away, while a stalled one fails the health.
+pub fn restarting<K, S, F>(
+    health: Arc<WatchHealth>,
+    start: F,
+) -> impl Stream<Item = watcher::Result<watcher::Event<K>>> + Send
+where
+    K: Send + 'static,
+    S: Stream<Item = watcher::Result<watcher::Event<K>>> + Send + 'static,
+    F: FnMut() -> S + Send + 'static,
+{
+    futures::stream::unfold(
+        (None::<EventStream<K>>, start, health),
+        |(mut current, mut start, health)| async move {
+            loop {
+                let stream = current.get_or_insert_with(|| start().boxed());
+                let next = if health.stall.is_zero() {
+                    Some(stream.next().await)
+                } else {
+                    tokio::time::timeout(health.stall, stream.next()).await.ok()
+                };
+                match next {
+                    Some(Some(event)) => {
+                        health.observe(&event);
+                        return Some((event, (current, start, health)));
+                    }
+                    Some(None) => warn!("Watch of {} ended, restart it.", health.name),
+                    None => warn!(
+                        "No events from the watch of {} for {}s, restart it.",
+                        health.name,
+                        health.stall.as_secs()
+                    ),
+                }
+                current = None;
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_watch_unhealthy() {
+        let health = WatchHealth::new("secrets", Duration::from_millis(10));
+        assert_eq!(health.problem(), None);
+        health.observe::<()>(&Err(watcher::Error::NoResourceVersion));
+        std::thread::sleep(Duration::from_millis(15));
+        assert!(health
+            .problem()
+            .unwrap()
+            .starts_with("watch of secrets failing"));
+        health.observe(&Ok(()));
+        assert_eq!(health.problem(), None);
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(health.problem().unwrap().starts_with("no events"));
+    }
+}
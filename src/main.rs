@@ -1,46 +1,138 @@
 #![deny(clippy::all)]
 use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
     process::exit,
-    sync::Arc,
+    sync::{Arc, OnceLock, RwLock},
     time::{Duration, SystemTime},
 };
 
-use elasticsearch::ElasticAdmin;
+use elasticsearch::{ConnectionConfig, ElasticAdmin, ElasticError};
 use error::OperatorError;
-use futures_util::StreamExt;
+use futures_util::{future, StreamExt};
 use k8s_openapi::{
     api::core::v1::Secret,
     apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+    NamespaceResourceScope,
 };
 use kube::{
-    api::{PatchParams, PostParams},
+    api::{ListParams, PartialObjectMeta, PatchParams, PostParams},
+    config::KubeConfigOptions,
     runtime::{
         controller::Action,
+        events::EventType,
         finalizer::{self, Event},
-        watcher, Controller,
+        metadata_watcher, predicates,
+        reflector::{self, Store},
+        watcher, Controller, Predicate, WatchStreamExt,
     },
-    Api, Client, CustomResourceExt, ResourceExt,
+    Api, Client, CustomResourceExt, Resource, ResourceExt,
 };
 use kube_derive::CustomResource;
 use log::{debug, error, info, warn};
+use reqwest::Certificate;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    env::load_env,
-    reconciliation::{apply_user, cleanup_user},
+    class::{ElasticsearchUserClass, PasswordPolicy},
+    cli::Command,
+    cloudevents::LifecycleEvent,
+    cluster_user::ClusterElasticsearchUser,
+    env::{load_env, Env},
+    error_report::ErrorReporter,
+    group::ElasticsearchUserGroup,
+    http::HttpState,
+    liveness::WatchHealth,
+    maintenance::Maintenance,
+    metrics::Metrics,
+    queue::WorkQueue,
+    reconciliation::{
+        applied_hash, apply_user, cleanup_user, delete_foreign_secret, expires_in,
+        operator_secret_values, requeue_interval, resolve_spec, user_api, users_referencing,
+    },
+    reload::Settings,
+    security::SecurityCheck,
+    status::ElasticSearchUserStatus,
 };
+mod audit;
+mod changes;
+mod class;
+mod cli;
+mod cloudevents;
+mod cluster_user;
+mod correlation;
+mod credentials;
+mod dashboard;
+mod drift;
 pub mod elasticsearch;
 mod env;
 mod error;
+mod error_report;
+mod events;
+#[cfg(test)]
+mod fixtures;
+mod group;
+mod http;
+mod liveness;
+mod maintenance;
+mod metrics;
+mod mock_elastic;
+mod notify;
+mod queue;
+mod rbac;
 mod reconciliation;
+mod reload;
+mod report;
+mod secret_format;
+mod security;
+mod shutdown;
+mod status;
 
+/// Deprecated, replaced by spec.keepOnDelete but still honored.
 pub const KEEP_ANNOTATION: &str = "eeops.io/keep";
+/// Annotate with "eeops.io/manage-secret": "false" to only read the
+/// credentials from the secret, same as spec.manageSecret: false.
+pub const MANAGE_SECRET_ANNOTATION: &str = "eeops.io/manage-secret";
+/// Annotate with "eeops.io/protected": "true" to refuse the cleanup
+/// on deletion, until the annotation is removed again.
+pub const PROTECTED_ANNOTATION: &str = "eeops.io/protected";
+/// Annotate with e.g. "eeops.io/requeue-seconds": "60" to verify
+/// the user more often than every REQUEUE_SECONDS.
+pub const REQUEUE_ANNOTATION: &str = "eeops.io/requeue-seconds";
+/// Set on namespaces to allow ElasticsearchUsers of the listed
+/// namespaces (comma separated, or "*") to write secrets into it.
+pub const ALLOW_SECRETS_FROM_ANNOTATION: &str = "eeops.io/allow-secrets-from";
+/// Marks secrets in other namespaces with the UID of the
+/// ElasticsearchUser, as owner references can't cross namespaces.
+pub const OWNED_BY_ANNOTATION: &str = "eeops.io/owned-by";
+/// Set on ElasticsearchUsers with spec.immutableSecret to
+/// the name of the current version of the secret.
+pub const CURRENT_SECRET_ANNOTATION: &str = "eeops.io/current-secret";
 pub const PASSWORD_LENGTH: usize = 24;
 pub const SECRET_USER: &str = "ELASTICSEARCH_USERNAME";
 pub const SECRET_PASS: &str = "ELASTICSEARCH_PASSWORD";
 pub const SECRET_URL: &str = "ELASTICSEARCH_URL";
+pub const SECRET_API_KEY: &str = "ELASTICSEARCH_API_KEY";
+pub const SECRET_BASIC_AUTH: &str = "ELASTICSEARCH_BASIC_AUTH";
+pub const SECRET_KIBANA_URL: &str = "KIBANA_URL";
+pub const SECRET_CA_CRT: &str = "ELASTICSEARCH_CA_CRT";
+pub const SECRET_CA_FINGERPRINT: &str = "ELASTICSEARCH_CA_FINGERPRINT";
+pub const DEFAULT_FINALIZER: &str = "ExtElasticOp";
+pub const DEFAULT_FIELD_MANAGER: &str = "eeops_field_manager";
+static FIELD_MANAGER: OnceLock<String> = OnceLock::new();
+
+/// Field manager of all server-side applies, see FIELD_MANAGER.
+pub fn field_manager() -> &'static str {
+    FIELD_MANAGER
+        .get()
+        .map_or(DEFAULT_FIELD_MANAGER, String::as_str)
+}
 pub const REQUEUE_SECONDS: u64 = 900; // reconcile everything every 15min
+pub const REQUEUE_CONFLICT_SECONDS: u64 = 5;
+pub const REQUEUE_UNAVAILABLE_SECONDS: u64 = 30;
+pub const REQUEUE_MAINTENANCE_SECONDS: u64 = 60;
+pub const STARTUP_BACKOFF_MAX_SECONDS: u64 = 60;
 
 #[derive(Deserialize, Serialize, Clone, Copy, Debug, JsonSchema)]
 enum UserPermissions {
@@ -49,147 +141,1160 @@ enum UserPermissions {
     Create,
 }
 
-/// Annotate with "eeops.io/keep": "true" to keep elastic search users.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+struct ApiKeySpec {
+    enabled: bool,
+}
+
+/// Key layout of the generated secret.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SecretFormat {
+    /// ELASTICSEARCH_USERNAME, ELASTICSEARCH_PASSWORD and ELASTICSEARCH_URL
+    #[default]
+    Default,
+    /// Keys and type following the Service Binding specification
+    /// (https://servicebinding.io), e.g. for OpenShift and Knative.
+    ServiceBinding,
+    /// output.elasticsearch.username, .password and .hosts as well as
+    /// ES_HOSTS, for Filebeat, Metricbeat and Logstash.
+    Beats,
+}
+
+/// Additional secret with the credentials of the user, e.g. in
+/// another format for a different workload.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretOutput {
+    pub name: String,
+    #[serde(default)]
+    pub format: SecretFormat,
+    #[serde(default)]
+    pub basic_auth_header: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection_uri_key: Option<String>,
+}
+
+/// Settings of the indices created by spec.bootstrapIndices.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapSpec {
+    /// ILM policy set as index.lifecycle.name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ilm_policy: Option<String>,
+    /// Create the alias <prefix>-write on the initial index as write
+    /// index, following the rollover pattern.
+    #[serde(default)]
+    pub write_alias: bool,
+}
+
+/// Grants the generated role to members of groups of a realm,
+/// e.g. of OIDC or LDAP.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleMappingSpec {
+    pub realm: String,
+    pub groups: Vec<String>,
+}
+
+/// Index management privileges on the indices starting with prefix,
+/// which must lie within spec.prefixes, e.g. for dated indices.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexManagementSpec {
+    pub prefix: String,
+    #[serde(default)]
+    pub manage: bool,
+    #[serde(default)]
+    pub create_index: bool,
+    #[serde(default)]
+    pub auto_configure: bool,
+}
+
+/// Global privileges of the role, by application names or patterns.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalPrivilegesSpec {
+    /// Manage the application privileges of these applications.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manage_applications: Vec<String>,
+    /// Write user profile data of these applications.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub write_profile_applications: Vec<String>,
+}
+
+/// Whether a native Elasticsearch user is created.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub enum IdentityMode {
+    /// Native user with the password of the secret, and its role.
+    #[default]
+    NativeUser,
+    /// Only the role (and role mappings), e.g. for shops authenticating
+    /// exclusively via SSO. No secret is written.
+    RoleOnly,
+}
+
+/// Source of spec.usernameFrom.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UsernameSource {
+    pub secret_key_ref: SecretKeyRef,
+}
+
+/// Key of a secret in the namespace of the ElasticsearchUser.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct SecretKeyRef {
+    pub name: String,
+    pub key: String,
+}
+
+/// What happens to the Elasticsearch user when the CR is deleted.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub enum CleanupMode {
+    #[default]
+    Delete,
+    /// Keep the user disabled, e.g. for retention requirements.
+    Disable,
+}
+
 #[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[kube(
     group = "eeops.io",
     version = "v1",
     kind = "ElasticsearchUser",
-    namespaced
+    namespaced,
+    shortname = "esuser",
+    shortname = "esu",
+    category = "elastic"
 )]
 #[kube(status = "ElasticSearchUserStatus")]
 #[serde(rename_all = "camelCase")]
 struct ElasticsearchUserSpec {
+    /// May contain {{ .metadata.name }}, {{ .metadata.namespace }}
+    /// and {{ .username }}, resolved at reconcile time.
+    /// Not needed with identityMode RoleOnly.
+    #[serde(default)]
     secret_ref: String,
+    /// Namespace of the secret, if not the one of the ElasticsearchUser.
+    /// It must allow this by annotation eeops.io/allow-secrets-from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_namespace: Option<String>,
+    /// Empty if taken from usernameFrom.
+    #[serde(default)]
     username: String,
+    /// Read the username from a secret instead, e.g. one
+    /// generated by another system.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username_from: Option<UsernameSource>,
     prefixes: Vec<String>,
-    permissions: UserPermissions,
+    /// Required, unless set by the class of classRef.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<UserPermissions>,
+    /// Index privileges passed verbatim to Elasticsearch instead of
+    /// those of permissions, e.g. ["read", "manage_follow_index"].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    privileges_raw: Vec<String>,
+    /// Additionally grant monitor on the prefixes, e.g. for health checkers.
+    #[serde(default)]
+    monitor: bool,
+    /// Additionally grant view_index_metadata on the prefixes,
+    /// e.g. for dashboards listing indices and mappings.
+    #[serde(default)]
+    view_index_metadata: bool,
+    /// Additionally grant maintenance (refresh, flush, force merge)
+    /// on the prefixes, e.g. for housekeeping cronjobs.
+    #[serde(default)]
+    maintenance: bool,
+    /// Additionally grant manage, create_index or auto_configure
+    /// on some of the prefixes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    index_management: Vec<IndexManagementSpec>,
+    /// Privileges of the role not bound to indices.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    global: Option<GlobalPrivilegesSpec>,
+    /// Overrides the operator's CLEANUP_MODE for this user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cleanup: Option<CleanupMode>,
+    /// Keep the Elasticsearch user and role when the
+    /// ElasticsearchUser is deleted. Replaces annotation eeops.io/keep.
+    #[serde(default)]
+    keep_on_delete: bool,
+    /// RFC 3339 time after which the Elasticsearch user is
+    /// deleted or disabled, depending on the cleanup mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    /// Additionally create an API key limited to the user's
+    /// privileges, stored in the secret and rotated with the password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_key: Option<ApiKeySpec>,
+    /// Add ELASTICSEARCH_BASIC_AUTH with the ready-to-use
+    /// Authorization header value to the secret.
+    #[serde(default)]
+    basic_auth_header: bool,
+    /// Defaults to the one of the class of classRef, or default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_format: Option<SecretFormat>,
+    /// Key receiving the connection URI including the
+    /// credentials, e.g. ELASTICSEARCH_DSN.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    connection_uri_key: Option<String>,
+    /// False to only read username and password from the secret,
+    /// maintained by someone else. Defaults to true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manage_secret: Option<bool>,
+    /// Create the secret immutable, as <secretRef>-v<N>. Changes create
+    /// the next version, see annotation eeops.io/current-secret.
+    #[serde(default)]
+    immutable_secret: bool,
+    /// Create an initial index per prefix, unless an index, alias or
+    /// data stream matches it, e.g. where auto-create is disabled.
+    #[serde(default)]
+    bootstrap_indices: bool,
+    /// Settings of bootstrapped indices, implies bootstrapIndices.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bootstrap: Option<BootstrapSpec>,
+    #[serde(default)]
+    identity_mode: IdentityMode,
+    /// Additional metadata of the native user, e.g. used by realm
+    /// lookups. Keys set by the operator can't be overridden.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    user_metadata: BTreeMap<String, String>,
+    /// Grant the generated role to SSO users, by one role
+    /// mapping per ElasticsearchUser.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    role_mappings: Vec<RoleMappingSpec>,
+    /// Additional secrets with the same credentials, owned by the CR.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    secrets: Vec<SecretOutput>,
+    /// Role shared with other ElasticsearchUsers, e.g. the members of an
+    /// ElasticsearchUserGroup. Defaults to role-<username>.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role_name: Option<String>,
+    /// ElasticsearchUserClass providing defaults and policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    class_ref: Option<String>,
+    /// Set from the class of classRef, not part of the CRD.
+    #[serde(skip)]
+    password_policy: Option<PasswordPolicy>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
-#[serde(rename_all = "camelCase")]
-pub struct ElasticSearchUserStatus {
-    ok: bool,
-    error_message: Option<String>,
+impl ElasticsearchUser {
+    /// Username of the Elasticsearch user. With spec.usernameFrom, unless
+    /// resolved, the one last applied, empty if never applied.
+    pub fn username(&self) -> &str {
+        if !self.spec.username.is_empty() {
+            return &self.spec.username;
+        }
+        let status = self.status.as_ref();
+        status
+            .and_then(|s| s.username.as_deref())
+            .unwrap_or_default()
+    }
+    /// spec.keepOnDelete, or the deprecated annotation eeops.io/keep.
+    pub fn keep_on_delete(&self) -> bool {
+        let annotation = self.annotations().get(KEEP_ANNOTATION);
+        self.spec.keep_on_delete || annotation.and_then(|v| env::as_bool(v)) == Some(true)
+    }
 }
 
-impl ElasticSearchUserStatus {
-    pub fn ok() -> Self {
-        Self {
-            ok: true,
-            error_message: None,
-        }
+impl ElasticsearchUserSpec {
+    fn secret_format(&self) -> SecretFormat {
+        self.secret_format.unwrap_or_default()
     }
-    pub fn err(msg: impl ToString) -> Self {
-        Self {
-            ok: false,
-            error_message: Some(msg.to_string()),
+    /// Output settings of the secret referenced by secretRef.
+    fn primary_output(&self) -> SecretOutput {
+        SecretOutput {
+            name: self.secret_ref.clone(),
+            format: self.secret_format(),
+            basic_auth_header: self.basic_auth_header,
+            connection_uri_key: self.connection_uri_key.clone(),
         }
     }
 }
 
-fn get_log_level() -> Result<log::LevelFilter, String> {
-    let var = std::env::var("LOGLEVEL").map(|e| e.to_lowercase());
-    let var = var.as_ref().map(|x| x.as_str());
-    match var {
-        Err(_) => Err("".to_string()),
-        Ok("trace") => Ok(log::LevelFilter::Trace),
-        Ok("debug") => Ok(log::LevelFilter::Debug),
-        Ok("info") => Ok(log::LevelFilter::Info),
-        Ok("warn") | Ok("warning") => Ok(log::LevelFilter::Warn),
-        Ok("error") => Ok(log::LevelFilter::Error),
-        Ok(unknown) => Err(unknown.to_string()),
+pub fn parse_log_level(level: &str) -> Option<log::LevelFilter> {
+    match level.trim().to_lowercase().as_str() {
+        "trace" => Some(log::LevelFilter::Trace),
+        "debug" => Some(log::LevelFilter::Debug),
+        "info" => Some(log::LevelFilter::Info),
+        "warn" | "warning" => Some(log::LevelFilter::Warn),
+        "error" => Some(log::LevelFilter::Error),
+        _ => None,
     }
 }
 
-fn setup_logger() -> Result<(), fern::InitError> {
+fn get_log_level() -> Result<log::LevelFilter, String> {
+    let var = std::env::var("LOGLEVEL").map_err(|_| "".to_string())?;
+    parse_log_level(&var).ok_or(var.to_lowercase())
+}
+
+/// Commands printing a result log to stderr, to keep it parseable.
+fn setup_logger(to_stderr: bool) -> Result<(), fern::InitError> {
+    let output: fern::Output = match to_stderr {
+        true => std::io::stderr().into(),
+        false => std::io::stdout().into(),
+    };
     fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
+        .format(|out, message, record| match correlation::current() {
+            Some(id) => out.finish(format_args!(
+                "[{} {} {} {}] {}",
+                humantime::format_rfc3339_seconds(SystemTime::now()),
+                record.level(),
+                record.target(),
+                id,
+                message
+            )),
+            None => out.finish(format_args!(
                 "[{} {} {}] {}",
                 humantime::format_rfc3339_seconds(SystemTime::now()),
                 record.level(),
                 record.target(),
                 message
-            ))
+            )),
         })
         .filter(|event| event.target().starts_with("ext_elasticsearch_operator"))
-        .level(get_log_level().unwrap_or(log::LevelFilter::Debug))
-        .chain(std::io::stdout())
+        // Filtering by level happens via log::max_level,
+        // so it can be changed at runtime (PUT /loglevel)
+        .level(log::LevelFilter::Trace)
+        .chain(output)
         .apply()?;
+    log::set_max_level(get_log_level().unwrap_or(log::LevelFilter::Debug));
     Ok(())
 }
 
-async fn load_elastic_search() -> ElasticAdmin {
-    let env = load_env();
-    if let Err(e) = env {
-        error!("Error loading environment: {}", e);
-        exit(1);
-    }
-    let env = env.unwrap();
+async fn load_elastic_search(env: &Env) -> ElasticAdmin {
     info!("Starting External Elasticsearch Operator.");
-    let el = ElasticAdmin::new(
-        &env.url,
-        env.username,
-        env.password,
-        env.skip_tls_cert_verify,
-    );
-    if let Err(e) = el.connection_ok().await {
-        error!("Error while checking ElasticSearch connection: {}.", e);
-        exit(1);
+    let ca_certificate = env.ca_certificate.as_ref().map(|pem| {
+        Certificate::from_pem(pem.as_bytes()).unwrap_or_else(|e| {
+            error!("Invalid CA certificate: {}", e);
+            exit(1);
+        })
+    });
+    let connection = ConnectionConfig {
+        skip_verify: env.skip_tls_cert_verify,
+        ca_certificate,
+        pool_idle_timeout: env.pool_idle_timeout,
+        pool_max_idle_per_host: env.pool_max_idle_per_host,
+        tcp_keepalive: env.tcp_keepalive,
+        http2: env.http2,
+        connect_timeout: env.connect_timeout,
+        request_timeout: env.request_timeout,
+        reconnect_after: env.reconnect_after,
+        instance_id: env.instance_id.clone(),
+        pod: env.pod.clone(),
+    };
+    let el = ElasticAdmin::new(&env.url, &env.username, &env.password, connection);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match el.connection_ok().await {
+            Ok(()) => {
+                if el.is_serverless() {
+                    info!("Connected to Elasticsearch Serverless, native users are unavailable.");
+                }
+                return el;
+            }
+            // Elasticsearch might just not be up yet
+            Err(ElasticError::HttpRequest(e)) if attempt < env.startup_retries => {
+                let delay = startup_backoff(attempt);
+                warn!(
+                    "Elasticsearch not reachable ({}/{}): {}. Retry in {}s.",
+                    attempt,
+                    env.startup_retries,
+                    e,
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                error!("Error while checking ElasticSearch connection: {}.", e);
+                exit(1);
+            }
+        }
+    }
+}
+
+/// Random share of the periodic requeue interval it is shortened by, so
+/// the ElasticsearchUsers reconciled together after a restart drift apart
+/// instead of re-checking Elasticsearch in lockstep every interval.
+const REQUEUE_SPLAY: f64 = 0.2;
+
+/// Between 80% and 100% of the interval, never later than configured.
+fn splay(interval: Duration) -> Duration {
+    interval.mul_f64(1.0 - rand::random::<f64>() * REQUEUE_SPLAY)
+}
+
+/// Exponential backoff for startup retries, capped at one minute.
+fn startup_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(
+        2u64.saturating_pow(attempt.min(6))
+            .min(STARTUP_BACKOFF_MAX_SECONDS),
+    )
+}
+
+/// CRDs of the operator, installed by install-crds or MANAGE_CRDS.
+fn operator_crds() -> [CustomResourceDefinition; 4] {
+    [
+        ElasticsearchUser::crd(),
+        ElasticsearchUserGroup::crd(),
+        ElasticsearchUserClass::crd(),
+        ClusterElasticsearchUser::crd(),
+    ]
+}
+
+/// Create the CRDs, or patch them if they already exist.
+async fn install_crd(client: &Client) -> Result<(), kube::Error> {
+    let crds: Api<CustomResourceDefinition> = Api::all(client.clone());
+    for crd in operator_crds() {
+        let name = crd.name_any();
+        match crds.create(&PostParams::default(), &crd).await {
+            Ok(_) => info!("CRD {} created/updates successfully", name),
+            Err(kube::Error::Api(ae)) if ae.code == 409 => {
+                if let Err(e) = patch_crd(&crds, &crd).await {
+                    warn!("Could not patch already existing CRD {}: {}", name, e);
+                    warn!(
+                        "If problems persist, consider deleting the CRD and restarting this operator."
+                    );
+                }
+                info!("Successfully patched existing CRD {}", name);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+async fn patch_crd(
+    crds: &Api<CustomResourceDefinition>,
+    crd: &CustomResourceDefinition,
+) -> Result<(), kube::Error> {
+    let patch_params = PatchParams::apply(field_manager()).force();
+    crds.patch(
+        &crd.name_any(),
+        &patch_params,
+        &kube::api::Patch::Apply(crd),
+    )
+    .await?;
+    Ok(())
+}
+
+/// In-cluster config, or of KUBECONFIG (default ~/.kube/config). With a
+/// context, always of the kubeconfig, e.g. to run the operator locally.
+async fn kube_config(context: Option<&str>) -> Result<kube::Config, String> {
+    match context {
+        Some(context) => {
+            let options = KubeConfigOptions {
+                context: Some(context.to_string()),
+                ..Default::default()
+            };
+            kube::Config::from_kubeconfig(&options)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        None => kube::Config::infer().await.map_err(|e| e.to_string()),
+    }
+}
+
+async fn kube_client(context: Option<&str>) -> Client {
+    let client = kube_config(context)
+        .await
+        .and_then(|config| Client::try_from(config).map_err(|e| e.to_string()));
+    match client {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Error connecting to kubernetes: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// The install-crds command: create or update the CRD and exit, e.g. as
+/// pre-install Job with permissions for CRDs, while the operator itself
+/// runs with MANAGE_CRDS=false. Unlike on startup, failed patches fail.
+async fn install_crds_and_exit(kube_context: Option<&str>) -> ! {
+    // Without the Elasticsearch configuration, which is not needed here
+    if let Ok(name) = std::env::var("FIELD_MANAGER") {
+        let _ = FIELD_MANAGER.set(name);
+    }
+    let client = kube_client(kube_context).await;
+    let crds: Api<CustomResourceDefinition> = Api::all(client);
+    for crd in operator_crds() {
+        let result = match crds.create(&PostParams::default(), &crd).await {
+            Err(kube::Error::Api(ae)) if ae.code == 409 => patch_crd(&crds, &crd).await,
+            result => result.map(|_| ()),
+        };
+        match result {
+            Ok(()) => info!("CRD {} installed.", crd.name_any()),
+            Err(e) => {
+                error!("Error installing CRD {}: {}", crd.name_any(), e);
+                exit(1);
+            }
+        }
+    }
+    exit(0);
+}
+
+/// With MANAGE_CRDS=false, the CRDs are installed by others. Only check
+/// they are there, which also works without permissions for CRDs.
+async fn check_crd(client: &Client, cluster_users: bool) -> Result<(), kube::Error> {
+    let users: Api<ElasticsearchUser> = Api::default_namespaced(client.clone());
+    users.list_metadata(&ListParams::default().limit(1)).await?;
+    let groups: Api<ElasticsearchUserGroup> = Api::default_namespaced(client.clone());
+    groups
+        .list_metadata(&ListParams::default().limit(1))
+        .await?;
+    let classes: Api<ElasticsearchUserClass> = Api::all(client.clone());
+    classes
+        .list_metadata(&ListParams::default().limit(1))
+        .await?;
+    if cluster_users {
+        let cluster_users: Api<ClusterElasticsearchUser> = Api::all(client.clone());
+        cluster_users
+            .list_metadata(&ListParams::default().limit(1))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Client errors (except throttling) won't go away by retrying.
+fn is_transient_kube_error(error: &kube::Error) -> bool {
+    match error {
+        kube::Error::Api(ae) => ae.code >= 500 || ae.code == 429,
+        _ => true,
+    }
+}
+
+async fn connect_kubernetes(env: &Env, kube_context: Option<&str>) -> Client {
+    let client = kube_client(kube_context).await;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = if env.manage_crds {
+            install_crd(&client).await
+        } else {
+            check_crd(&client, env.cluster_users).await
+        };
+        match result {
+            Ok(()) => return client,
+            Err(e) if is_transient_kube_error(&e) && attempt < env.startup_retries => {
+                let delay = startup_backoff(attempt);
+                warn!(
+                    "Kubernetes API not reachable ({}/{}): {}. Retry in {}s.",
+                    attempt,
+                    env.startup_retries,
+                    e,
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) if env.manage_crds => {
+                error!("Error posting the CRDs: {}", e);
+                exit(1);
+            }
+            Err(e) => {
+                error!(
+                    "ElasticsearchUser, ElasticsearchUserGroup, ElasticsearchUserClass or \
+                    ClusterElasticsearchUser CRD not installed or not accessible: {}",
+                    e
+                );
+                exit(1);
+            }
+        }
     }
-    el
 }
 
 pub struct Context {
     pub client: Client,
     pub elastic: ElasticAdmin,
+    /// Swapped on reload, see [`Context::settings`].
+    settings: RwLock<Arc<Settings>>,
+    pub maintenance: Maintenance,
+    /// Whether users can be provisioned at all, see [`SecurityCheck`].
+    pub security: SecurityCheck,
+    pub metrics: Arc<Metrics>,
+    /// Orders reconciles, see [`WorkQueue`].
+    pub queue: Arc<WorkQueue>,
+    pub error_reporter: Arc<ErrorReporter>,
+    /// Operator-wide values written into every generated secret.
+    pub secret_values: BTreeMap<String, String>,
+    /// Metadata of the secrets in the namespace, without their data.
+    pub secrets: Store<PartialObjectMeta<Secret>>,
+    /// ElasticsearchUserClasses, see spec.classRef.
+    pub classes: Store<ElasticsearchUserClass>,
+}
+
+impl Context {
+    /// Current configuration. Reconciles keep the snapshot
+    /// they started with, also if it is reloaded meanwhile.
+    pub fn settings(&self) -> Arc<Settings> {
+        self.settings
+            .read()
+            .expect("Settings lock poisoned")
+            .clone()
+    }
+    pub fn set_settings(&self, settings: Settings) {
+        *self.settings.write().expect("Settings lock poisoned") = Arc::new(settings);
+    }
+}
+
+/// Whether HEALTH_GATE is enabled and the cluster is red.
+/// If the health can't be read, mutations are attempted anyway.
+async fn cluster_red(context: &Context) -> bool {
+    if !context.settings().env.health_gate {
+        return false;
+    }
+    match context.elastic.cluster_health().await {
+        Ok(status) => status.as_deref() == Some("red"),
+        Err(e) => {
+            warn!("Could not check the cluster health: {}", e);
+            false
+        }
+    }
+}
+
+/// Reason users can't be provisioned. Re-probed while failing,
+/// to pick up a fixed license without an operator restart.
+async fn security_error(context: &Context) -> Option<String> {
+    context.security.error()?;
+    context.security.probe(&context.elastic).await
+}
+
+/// Delete or disable the Elasticsearch user, depending on the cleanup mode.
+async fn remove_user(user: &ElasticsearchUser, context: &Context) -> Result<(), OperatorError> {
+    let user = applied_user(user);
+    // The cleanup mode of the class, unless the class is gone
+    let class = class::cached_class(&user, &context.classes);
+    let user = &class::apply_class(&user, class.as_ref()).unwrap_or_else(|_| user.clone());
+    if user.spec.username.is_empty() {
+        debug!(
+            "ElasticsearchUser {} has no username applied, nothing to remove.",
+            user.name_any()
+        );
+        return Ok(());
+    }
+    let settings = context.settings();
+    let mode = user.spec.cleanup.unwrap_or(settings.env.cleanup_mode);
+    if cleanup_user(user, mode, &context.elastic, &settings.audit).await? {
+        let event = match mode {
+            CleanupMode::Delete => LifecycleEvent::UserDeleted,
+            CleanupMode::Disable => LifecycleEvent::UserDisabled,
+        };
+        settings.cloud_events.emit(event, user).await;
+    }
+    Ok(())
+}
+
+/// Whether deletion protection is enabled, see PROTECTED_ANNOTATION.
+fn is_protected(user: &ElasticsearchUser) -> bool {
+    let annotation = user.annotations().get(PROTECTED_ANNOTATION);
+    annotation.and_then(|v| env::as_bool(v)) == Some(true)
+}
+
+/// The ElasticsearchUser with the username last applied, as the
+/// secret of spec.usernameFrom may be gone or changed by now.
+fn applied_user(user: &ElasticsearchUser) -> ElasticsearchUser {
+    let mut user = user.clone();
+    user.spec.username = user.username().to_string();
+    user
+}
+
+/// Remove the Elasticsearch user of an ElasticsearchUser past
+/// spec.expiresAt and mark it expired, until the CR is changed.
+async fn expire_user(
+    user: &ElasticsearchUser,
+    context: &Context,
+    api: &Api<ElasticsearchUser>,
+) -> Result<Action, OperatorError> {
+    let already_expired = user.status.as_ref().is_some_and(|s| {
+        s.conditions
+            .iter()
+            .any(|c| c.type_ == status::CONDITION_EXPIRED)
+    });
+    if already_expired {
+        // Avoid status updates triggering reconciles over and over
+        return Ok(Action::await_change());
+    }
+    remove_user(user, context).await?;
+    let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let mut status = user.status.clone().unwrap_or_default();
+    let expires_at = user.spec.expires_at.clone().unwrap_or_default();
+    status.set_expired(&now, &expires_at);
+    info!("ElasticsearchUser {} expired", user.name_any());
+    events::publish(
+        &context.client,
+        user,
+        EventType::Normal,
+        "Expired",
+        format!("Elasticsearch user removed, expired at {}", expires_at),
+    )
+    .await;
+    status::patch_status(api, user, &status).await?;
+    Ok(Action::await_change())
+}
+
+/// Whether the last successful sync had the same inputs and
+/// is recent enough to skip the checks against Elasticsearch.
+fn is_unchanged(
+    user: &ElasticsearchUser,
+    hash: Option<&str>,
+    full_sync_interval: Duration,
+) -> bool {
+    let Some(status) = user.status.as_ref() else {
+        return false;
+    };
+    let Some(hash) = hash else {
+        return false;
+    };
+    let last_sync = status
+        .last_successful_sync
+        .as_deref()
+        .and_then(|t| humantime::parse_rfc3339(t).ok());
+    let recent = last_sync
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .is_some_and(|age| age < full_sync_interval);
+    status.ok && status.applied_hash.as_deref() == Some(hash) && recent
 }
 
 async fn reconcile(
     user: Arc<ElasticsearchUser>,
     context: Arc<Context>,
 ) -> Result<Action, finalizer::Error<OperatorError>> {
-    let api: Api<ElasticsearchUser> = Api::default_namespaced(context.client.clone());
+    let id = correlation::new_id();
+    debug!("Reconcile ElasticsearchUser {} as {}", user.name_any(), id);
+    correlation::scope(id, reconcile_user(user, context)).await
+}
+
+async fn reconcile_user(
+    user: Arc<ElasticsearchUser>,
+    context: Arc<Context>,
+) -> Result<Action, finalizer::Error<OperatorError>> {
+    let namespace = user.namespace().unwrap_or_default();
+    let own_namespace = context.client.default_namespace();
+    if !context
+        .settings()
+        .env
+        .namespaces
+        .enabled(&namespace, own_namespace)
+    {
+        // Left alone, including the finalizer
+        debug!(
+            "Namespace {} excluded by WATCH_NAMESPACES/EXCLUDE_NAMESPACES, skip {}.",
+            namespace,
+            user.name_any()
+        );
+        return Ok(Action::await_change());
+    }
+    let api: Api<ElasticsearchUser> = user_api(&user, &context.client);
 
     let rec = |event: Event<ElasticsearchUser>| async {
-        let api: Api<ElasticsearchUser> = Api::default_namespaced(context.client.clone());
+        let api = api.clone();
+        let settings = context.settings();
 
         match event {
-            Event::Cleanup(user) => cleanup_user(&user, &context.client, &context.elastic).await?,
+            Event::Cleanup(user) if is_protected(&user) => {
+                events::publish(
+                    &context.client,
+                    &user,
+                    EventType::Warning,
+                    "DeletionProtected",
+                    format!(
+                        "Cleanup refused, remove the annotation {} to delete",
+                        PROTECTED_ANNOTATION
+                    ),
+                )
+                .await;
+                // Keep the finalizer until the annotation is removed
+                Err(OperatorError::Protected)
+            }
+            Event::Cleanup(user) if context.maintenance.is_active() => {
+                events::publish(
+                    &context.client,
+                    &user,
+                    EventType::Normal,
+                    "MaintenancePause",
+                    "Cleanup postponed until the maintenance mode ends".to_string(),
+                )
+                .await;
+                // Keep the finalizer until cleanup can be done
+                Err(OperatorError::Maintenance)
+            }
+            Event::Apply(user) if context.maintenance.is_active() => {
+                let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+                let mut status = user.status.clone().unwrap_or_default();
+                if status.set_paused(&now) {
+                    events::publish(
+                        &context.client,
+                        &user,
+                        EventType::Normal,
+                        "MaintenancePause",
+                        "Reconciliation paused by the maintenance mode".to_string(),
+                    )
+                    .await;
+                    status::patch_status(&api, &user, &status).await?;
+                }
+                Ok(Action::requeue(Duration::from_secs(
+                    REQUEUE_MAINTENANCE_SECONDS,
+                )))
+            }
+            Event::Cleanup(_) if cluster_red(&context).await => {
+                // Keep the finalizer until the cluster recovered
+                Err(OperatorError::ElasticsearchUnavailable(
+                    "cluster health is red".to_string(),
+                ))
+            }
+            Event::Cleanup(user) => {
+                let _permit = context.queue.acquire(true).await;
+                if user.keep_on_delete() {
+                    info!(
+                        "ElasticsearchUser {} is kept on delete, leave user {} in Elasticsearch.",
+                        user.name_any(),
+                        user.username()
+                    );
+                } else {
+                    remove_user(&user, &context).await?;
+                }
+                delete_foreign_secret(&user, &context.client).await?;
+                Ok(Action::requeue(Duration::from_secs(REQUEUE_SECONDS)))
+            }
+            Event::Apply(user) if cluster_red(&context).await => {
+                let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+                let mut status = user.status.clone().unwrap_or_default();
+                if status.set_degraded(&now) {
+                    events::publish(
+                        &context.client,
+                        &user,
+                        EventType::Warning,
+                        "ClusterRed",
+                        "Reconciliation skipped while the cluster health is red".to_string(),
+                    )
+                    .await;
+                    status::patch_status(&api, &user, &status).await?;
+                }
+                Ok(Action::requeue(Duration::from_secs(
+                    REQUEUE_UNAVAILABLE_SECONDS,
+                )))
+            }
             Event::Apply(user) => {
-                let result = apply_user(&user, &context.client, &context.elastic).await;
-                let mut user = (*user).clone();
-                match result {
-                    Ok(_) => user.status = Some(ElasticSearchUserStatus::ok()),
-                    Err(e) => user.status = Some(ElasticSearchUserStatus::err(e)),
+                let expires_in = match expires_in(&user) {
+                    Ok(expires_in) => expires_in,
+                    Err(e) => {
+                        // Invalid expiresAt, reported by apply_user
+                        debug!("{}", e);
+                        None
+                    }
+                };
+                if expires_in == Some(Duration::ZERO) {
+                    return expire_user(&user, &context, &api).await;
                 }
-                let pp = PostParams::default();
-                api.replace_status(
-                    user.name_any().as_str(),
-                    &pp,
-                    serde_json::to_vec(&user).expect("Serde JSON failed to serialize status"),
-                )
-                .await?;
+                // Invalid annotation, reported by apply_user
+                let interval = requeue_interval(&user).ok().flatten();
+                // Recheck exactly at expiry
+                let requeue = expires_in.unwrap_or(Duration::MAX).min(splay(
+                    interval.unwrap_or(Duration::from_secs(REQUEUE_SECONDS)),
+                ));
+                let full_sync_interval = interval.map_or(settings.env.full_sync_interval, |i| {
+                    i.min(settings.env.full_sync_interval)
+                });
+                let hash = applied_hash(
+                    &user,
+                    &context.secrets,
+                    &context.elastic.url,
+                    &context.secret_values,
+                    &context.classes,
+                );
+                if is_unchanged(&user, hash.as_deref(), full_sync_interval) {
+                    debug!(
+                        "ElasticsearchUser {} unchanged since last sync, skip checks.",
+                        user.name_any()
+                    );
+                    return Ok(Action::requeue(requeue));
+                }
+                // New or edited spec or secret, ahead of routine re-checks
+                let changed =
+                    user.status.as_ref().and_then(|s| s.applied_hash.as_deref()) != hash.as_deref();
+                let _permit = context.queue.acquire(changed).await;
+                let result = match security_error(&context).await {
+                    Some(reason) => Err(OperatorError::SecurityUnavailable(reason)),
+                    None => resolve_spec(&user, &context.client, &context.classes).await,
+                };
+                let result = match result {
+                    Ok(resolved) => {
+                        let result = apply_user(
+                            &resolved,
+                            &context.client,
+                            &context.elastic,
+                            &context.secret_values,
+                            &settings.audit,
+                        )
+                        .await;
+                        let previous = applied_user(&user);
+                        if result.is_ok()
+                            && !previous.spec.username.is_empty()
+                            && previous.spec.username != resolved.spec.username
+                        {
+                            info!(
+                                "Username of {} changed from {} to {}, remove the previous user.",
+                                user.name_any(),
+                                previous.spec.username,
+                                resolved.spec.username
+                            );
+                            remove_user(&previous, &context).await?;
+                        }
+                        result.map(|outcome| (outcome, resolved))
+                    }
+                    Err(e) => Err(e),
+                };
+                let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+                let mut status = user.status.clone().unwrap_or_default();
+                let action = match result {
+                    Ok((outcome, resolved)) => {
+                        status.set_ok(&now, &outcome);
+                        status.username = Some(resolved.spec.username.clone());
+                        status.applied_hash = hash;
+                        status.binding = (resolved.spec.secret_format()
+                            == SecretFormat::ServiceBinding)
+                            .then(|| status::Binding {
+                                name: reconciliation::current_secret_name(&resolved),
+                            });
+                        status.secret_outputs =
+                            user.spec.secrets.iter().map(|s| s.name.clone()).collect();
+                        if !outcome.changes.is_empty() {
+                            events::publish(
+                                &context.client,
+                                &user,
+                                EventType::Normal,
+                                "Applied",
+                                changes::describe(&outcome.changes),
+                            )
+                            .await;
+                        }
+                        if outcome.user_created {
+                            settings
+                                .cloud_events
+                                .emit(LifecycleEvent::UserCreated, &resolved)
+                                .await;
+                        }
+                        if outcome.password_rotated {
+                            settings
+                                .cloud_events
+                                .emit(LifecycleEvent::PasswordRotated, &resolved)
+                                .await;
+                        }
+                        Action::requeue(requeue)
+                    }
+                    Err(e) => {
+                        status.set_err(&now, &e);
+                        context.metrics.record_failure(e.code());
+                        context.error_reporter.reconcile_failed(&user, &e);
+                        let failures = status.consecutive_failures;
+                        let threshold = settings.env.failure_threshold;
+                        if failures == threshold {
+                            events::publish(
+                                &context.client,
+                                &user,
+                                EventType::Warning,
+                                "RepeatedFailures",
+                                format!(
+                                    "Reconciliation failed {} times in a row, \
+                                    retry every {}s from now on: {}",
+                                    failures,
+                                    settings.env.failure_backoff.as_secs(),
+                                    e
+                                ),
+                            )
+                            .await;
+                        }
+                        // Notify when starting to fail and when degraded,
+                        // not on every retry
+                        if failures == 1 || failures == threshold {
+                            settings
+                                .notifier
+                                .reconcile_failed(
+                                    &user,
+                                    &e.to_string(),
+                                    failures,
+                                    failures == threshold,
+                                )
+                                .await;
+                        }
+                        match e.retry_after() {
+                            // Back off to spare Elasticsearch and the logs
+                            Some(_) if threshold > 0 && failures >= threshold => {
+                                Action::requeue(settings.env.failure_backoff)
+                            }
+                            Some(delay) => Action::requeue(delay),
+                            // Wait for the spec, the Secret or the class to
+                            // be changed, the status patch below is
+                            // filtered from the watch
+                            None => Action::await_change(),
+                        }
+                    }
+                };
+                status::patch_status(&api, &user, &status).await?;
+                Ok(action)
             }
         }
+    };
+    let settings = context.settings();
+    let (finalizer_name, user) = migrate_finalizer(
+        &api,
+        user,
+        &settings.env.finalizer,
+        &settings.env.legacy_finalizers,
+    )
+    .await
+    .map_err(finalizer::Error::AddFinalizer)?;
+    finalizer::finalizer(&api, finalizer_name, user, rec).await
+}
 
-        Ok(Action::requeue(Duration::from_secs(REQUEUE_SECONDS)))
+/// Finalizer to handle the ElasticsearchUser with. Finalizers of previous
+/// names are replaced by the current one. If the ElasticsearchUser is
+/// already being deleted, no finalizer can be added, so it is cleaned up
+/// under the previous name instead, to not get stuck in Terminating.
+async fn migrate_finalizer<'a>(
+    api: &Api<ElasticsearchUser>,
+    user: Arc<ElasticsearchUser>,
+    current: &'a str,
+    legacy: &'a [String],
+) -> Result<(&'a str, Arc<ElasticsearchUser>), kube::Error> {
+    let finalizers = user.finalizers();
+    let Some(previous) = legacy.iter().find(|name| finalizers.contains(name)) else {
+        return Ok((current, user));
     };
-    finalizer::finalizer(&api, "ExtElasticOp", user.clone(), rec).await
+    if user.metadata.deletion_timestamp.is_some() {
+        if finalizers.iter().any(|name| name == current) {
+            return Ok((current, user));
+        }
+        return Ok((previous, user));
+    }
+    let mut migrated: Vec<String> = finalizers
+        .iter()
+        .filter(|name| !legacy.contains(name))
+        .cloned()
+        .collect();
+    if !migrated.iter().any(|name| name == current) {
+        migrated.push(current.to_string());
+    }
+    info!(
+        "ElasticsearchUser {}: replace finalizer {} by {}",
+        user.name_any(),
+        previous,
+        current
+    );
+    // The resource version fails the patch if the finalizers changed meanwhile
+    let patch = serde_json::json!({
+        "metadata": {
+            "finalizers": migrated,
+            "resourceVersion": user.resource_version(),
+        }
+    });
+    let user = api
+        .patch(
+            &user.name_any(),
+            &PatchParams::default(),
+            &kube::api::Patch::Merge(&patch),
+        )
+        .await?;
+    Ok((current, Arc::new(user)))
+}
+
+/// Changes of the finalizers or the deletion, which the generation
+/// does not cover.
+fn finalizers(user: &ElasticsearchUser) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    user.finalizers().hash(&mut hasher);
+    user.metadata.deletion_timestamp.is_some().hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Changes of an ElasticsearchUser which need a reconcile.
+fn user_changed(user: &ElasticsearchUser) -> Option<u64> {
+    predicates::generation
+        .combine(predicates::annotations)
+        .combine(finalizers)
+        .hash_property(user)
 }
 
 fn error_policy(
-    _user: Arc<ElasticsearchUser>,
-    _error: &finalizer::Error<OperatorError>,
-    _context: Arc<Context>,
+    user: Arc<ElasticsearchUser>,
+    error: &finalizer::Error<OperatorError>,
+    context: Arc<Context>,
 ) -> Action {
-    Action::requeue(Duration::from_secs(REQUEUE_SECONDS))
+    match error {
+        finalizer::Error::ApplyFailed(e) | finalizer::Error::CleanupFailed(e) => {
+            context.metrics.record_failure(e.code());
+            context.error_reporter.reconcile_failed(&user, e);
+            Action::requeue(
+                e.retry_after()
+                    .unwrap_or(Duration::from_secs(REQUEUE_SECONDS)),
+            )
+        }
+        _ => Action::requeue(Duration::from_secs(REQUEUE_SECONDS)),
+    }
+}
+
+/// Api of the namespaces to reconcile, see WATCH_NAMESPACES: the
+/// operator's own, another single one, or all with a field selector for
+/// EXCLUDE_NAMESPACES. Lists are filtered by `enabled_namespaces`.
+fn watched_api<K>(client: &Client, env: &Env, own_namespace: &str) -> (Api<K>, watcher::Config)
+where
+    K: Resource<Scope = NamespaceResourceScope>,
+    <K as Resource>::DynamicType: Default,
+{
+    match env.namespaces.watch.as_slice() {
+        [] => (
+            Api::namespaced(client.clone(), own_namespace),
+            watcher::Config::default(),
+        ),
+        [namespace] if namespace != "*" => (
+            Api::namespaced(client.clone(), namespace),
+            watcher::Config::default(),
+        ),
+        _ => {
+            let excluded: Vec<String> = env
+                .namespaces
+                .exclude
+                .iter()
+                .map(|namespace| format!("metadata.namespace!={}", namespace))
+                .collect();
+            let config = match excluded.is_empty() {
+                true => watcher::Config::default(),
+                false => watcher::Config::default().fields(&excluded.join(",")),
+            };
+            (Api::all(client.clone()), config)
+        }
+    }
+}
+
+/// Drop the objects of namespaces which are not reconciled.
+fn enabled_namespaces<K: Resource>(
+    event: watcher::Event<K>,
+    enabled: impl Fn(&str) -> bool,
+) -> Option<watcher::Event<K>> {
+    let enabled = |object: &K| enabled(object.meta().namespace.as_deref().unwrap_or_default());
+    match event {
+        watcher::Event::Applied(object) | watcher::Event::Deleted(object) if !enabled(&object) => {
+            None
+        }
+        watcher::Event::Restarted(mut objects) => {
+            objects.retain(enabled);
+            Some(watcher::Event::Restarted(objects))
+        }
+        event => Some(event),
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    setup_logger().expect("Unable to setup logger.");
+    let args = match cli::parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            exit(2);
+        }
+    };
+    let to_stderr = !matches!(args.command, Command::Run);
+    setup_logger(to_stderr).expect("Unable to setup logger.");
     match get_log_level() {
         Ok(l) => info!("Loglevel set to {}.", l),
         Err(empty) if empty.is_empty() => info!("LOGLEVEL not set, fall back to debug."),
@@ -198,61 +1303,234 @@ async fn main() {
             other
         ),
     }
-    let elastic_admin = load_elastic_search().await;
-    info!("Connection to Elasticsearch established, credentials for superuser are working.");
-
-    let client = Client::try_default().await;
-    if let Err(e) = client {
-        error!("Error connecting to kubernetes: {}", e);
-        exit(1);
+    if let Command::InstallCrds = args.command {
+        install_crds_and_exit(args.kube_context.as_deref()).await;
     }
-    let client = client.unwrap();
-    info!("Connection to Kubernetes API established.");
-
-    let crds: Api<CustomResourceDefinition> = Api::all(client.clone());
-    match crds
-        .create(&PostParams::default(), &ElasticsearchUser::crd())
-        .await
-    {
-        Ok(_) => info!("ElasticsearchUser CRD created/updates successfully"),
-        Err(kube::Error::Api(ae)) if ae.code == 409 => {
-            let patch_params = PatchParams::apply("eeops_field_manager").force();
-            if let Err(e) = crds
-                .patch(
-                    ElasticsearchUser::crd_name(),
-                    &patch_params,
-                    &kube::api::Patch::Apply(ElasticsearchUser::crd()),
-                )
-                .await
-            {
-                warn!(
-                    "Could not patch already existing CRD ElasticsearchUser: {}",
-                    e
-                );
-                warn!(
-                    "If problems persist, consider deleting the CRD and restarting this operator."
-                );
+    let mock_elastic_url = match args.mock_elastic {
+        true => match mock_elastic::start() {
+            Ok(url) => Some(url),
+            Err(e) => {
+                error!("{}", e);
+                exit(1);
             }
-            info!(
-                "Successfully patched existing CRD {}",
-                ElasticsearchUser::crd_name()
-            );
+        },
+        false => None,
+    };
+    let env = match load_env(args.config.as_deref(), mock_elastic_url.clone()) {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Error loading environment: {}", e);
+            exit(1);
         }
+    };
+    if let Some(level) = env.log_level {
+        log::set_max_level(level);
+    }
+    elasticsearch::set_trace_http(env.trace_http);
+    elasticsearch::set_compression(env.compression);
+    let _ = FIELD_MANAGER.set(env.field_manager.clone());
+    if let Command::GenerateRbac = args.command {
+        // The namespace the operator would run in, without connecting
+        let namespace = kube_config(args.kube_context.as_deref())
+            .await
+            .map(|config| config.default_namespace)
+            .unwrap_or_else(|_| "default".to_string());
+        print!(
+            "{}",
+            rbac::manifests(&env, &args.service_account, &namespace)
+        );
+        return;
+    }
+    let error_reporter = match ErrorReporter::new(env.sentry_dsn.as_deref(), &env.url) {
+        Ok(reporter) => Arc::new(reporter),
         Err(e) => {
-            error!("Error posting ElasticsearchUser CRD: {}", e);
+            error!("Error loading environment: {}", e);
             exit(1);
         }
+    };
+    error_report::install_panic_hook(error_reporter.clone());
+    let metrics = Arc::new(Metrics::new());
+    let http_state = Arc::new(HttpState::new(
+        env.api_token.clone(),
+        env.dashboard,
+        metrics.clone(),
+    ));
+    if let Command::Run = args.command {
+        tokio::spawn(http::serve(env.http_port, http_state.clone()));
+        let namespace = match kube_config(args.kube_context.as_deref()).await {
+            Ok(config) => config.default_namespace,
+            Err(e) => {
+                error!("Error connecting to kubernetes: {}", e);
+                exit(1);
+            }
+        };
+        if !env.namespaces.enabled(&namespace, &namespace) && !env.namespaces.other_than(&namespace)
+        {
+            // Nothing to reconcile, e.g. the same configuration deployed to all namespaces
+            warn!(
+                "Namespace {} excluded by WATCH_NAMESPACES/EXCLUDE_NAMESPACES, not reconciling.",
+                namespace
+            );
+            http_state.set_ready(true);
+            shutdown::terminate().await;
+            return;
+        }
+    }
+    let elastic_admin = load_elastic_search(&env).await;
+    info!("Connection to Elasticsearch established, credentials for superuser are working.");
+
+    if let Command::Status = args.command {
+        let client = kube_client(args.kube_context.as_deref()).await;
+        if let Err(e) = drift::print_drift_report(&client, &elastic_admin, &env).await {
+            error!("Error creating drift report: {}", e);
+            exit(1);
+        }
+        return;
+    }
+
+    let client = connect_kubernetes(&env, args.kube_context.as_deref()).await;
+    info!("Connection to Kubernetes API established.");
+    if let Ok(version) = elastic_admin.version().await {
+        error_reporter.set_elasticsearch_version(version);
     }
 
-    let elastic_users: Api<ElasticsearchUser> = Api::default_namespaced(client.clone());
-    let secret_api: Api<Secret> = Api::default_namespaced(client.clone());
+    let own_namespace = client.default_namespace().to_string();
+    let (elastic_users, user_watch_config) =
+        watched_api::<ElasticsearchUser>(&client, &env, &own_namespace);
+    let (secret_api, secret_watch_config) = watched_api::<Secret>(&client, &env, &own_namespace);
+    let namespaces = Arc::new(env.namespaces.clone());
+    let enabled = move |namespace: &str| namespaces.enabled(namespace, &own_namespace);
+    let user_enabled = enabled.clone();
+    let maintenance = Maintenance::new(env.maintenance_mode);
+    let security = SecurityCheck::new();
+    security.probe(&elastic_admin).await;
+    // The secret watch both triggers reconciles and caches the resource versions.
+    // Metadata only, to not hold every secret of the namespace in memory.
+    let (secrets, secret_writer) = reflector::store();
+    let secret_health = Arc::new(WatchHealth::new("Secrets", env.watch_stall));
+    http_state.add_watch(secret_health.clone());
+    let secret_stream = reflector::reflector(
+        secret_writer,
+        liveness::restarting(secret_health, move || {
+            let enabled = enabled.clone();
+            metadata_watcher(secret_api.clone(), secret_watch_config.clone()).filter_map(
+                move |event| {
+                    future::ready(event.map(|e| enabled_namespaces(e, &enabled)).transpose())
+                },
+            )
+        }),
+    )
+    .default_backoff()
+    .touched_objects();
+    // Only the matching ElasticsearchUsers are managed by this instance
+    let user_watch_config = match &env.watch_label_selector {
+        Some(selector) => user_watch_config.labels(selector),
+        None => user_watch_config,
+    };
+    let user_health = Arc::new(WatchHealth::new("ElasticsearchUsers", env.watch_stall));
+    http_state.add_watch(user_health.clone());
+    let (user_store, user_writer) = reflector::store();
+    let user_stream = reflector::reflector(
+        user_writer,
+        liveness::restarting(user_health, move || {
+            let enabled = user_enabled.clone();
+            watcher(elastic_users.clone(), user_watch_config.clone()).filter_map(move |event| {
+                future::ready(event.map(|e| enabled_namespaces(e, &enabled)).transpose())
+            })
+        }),
+    )
+    .applied_objects()
+    // Status patches of the reconcile must not trigger the next one,
+    // retries are scheduled by the returned Action.
+    .predicate_filter(user_changed);
+    // Classes are cluster-scoped, shared by all namespaces
+    let class_api: Api<ElasticsearchUserClass> = Api::all(client.clone());
+    let class_health = Arc::new(WatchHealth::new(
+        "ElasticsearchUserClasses",
+        env.watch_stall,
+    ));
+    http_state.add_watch(class_health.clone());
+    let (classes, class_writer) = reflector::store();
+    let class_stream = reflector::reflector(
+        class_writer,
+        liveness::restarting(class_health, move || {
+            watcher(class_api.clone(), watcher::Config::default())
+        }),
+    )
+    .default_backoff()
+    .touched_objects();
+    let secret_values = operator_secret_values(&env);
     let context = Arc::new(Context {
+        secret_values,
+        secrets,
+        classes,
+        maintenance,
+        security,
+        metrics,
+        queue: Arc::new(WorkQueue::new(env.concurrency)),
+        error_reporter,
         elastic: elastic_admin,
         client,
+        settings: RwLock::new(Arc::new(Settings::new(env))),
     });
-    Controller::new(elastic_users, watcher::Config::default())
-        .shutdown_on_signal()
-        .owns(secret_api, watcher::Config::default())
+    if let Some(configmap_name) = context.settings().env.maintenance_configmap.clone() {
+        tokio::spawn(maintenance::watch_maintenance(
+            configmap_name,
+            context.clone(),
+        ));
+    }
+    tokio::spawn(reload::watch_reload(
+        args.config.clone(),
+        mock_elastic_url,
+        context.clone(),
+    ));
+    // Only one replica creates the ElasticsearchUsers of the operator's namespace
+    let namespaces = &context.settings().env.namespaces;
+    let creates_users = namespaces.in_shard(context.client.default_namespace());
+    if creates_users {
+        tokio::spawn(group::run(
+            context.client.clone(),
+            context.settings().env.watch_label_selector.clone(),
+        ));
+    }
+    if context.settings().env.cluster_users && creates_users {
+        tokio::spawn(cluster_user::run(
+            context.client.clone(),
+            context.settings().env.watch_label_selector.clone(),
+        ));
+    }
+    if let Some(secret_name) = context.settings().env.credentials_secret.clone() {
+        tokio::spawn(credentials::watch_credentials(secret_name, context.clone()));
+    }
+    let controller = Controller::for_stream(user_stream, user_store);
+    http_state.set_users(controller.store());
+    // One report per replica, of its shard
+    if let Some(configmap_name) = &context.settings().env.status_configmap {
+        tokio::spawn(report::publish_reports(
+            namespaces.shard_name(configmap_name),
+            controller.store(),
+            context.clone(),
+        ));
+    }
+    if let Some(configmap_name) = &context.settings().env.namespace_status_configmap {
+        tokio::spawn(report::publish_namespace_reports(
+            namespaces.shard_name(configmap_name),
+            controller.store(),
+            context.clone(),
+        ));
+    }
+    let users = controller.store();
+    let class_users = controller.store();
+    let shutdown = shutdown::on_signal(http_state.clone(), context.clone());
+    http_state.set_ready(true);
+    controller
+        .graceful_shutdown_on(shutdown)
+        .watches_stream(secret_stream, move |secret| {
+            users_referencing(&secret, &users)
+        })
+        .watches_stream(class_stream, move |class| {
+            class::users_of_class(&class, &class_users)
+        })
         .run(reconcile, error_policy, context)
         .for_each(|res| async move {
             match res {
@@ -261,4 +1539,69 @@ async fn main() {
             }
         })
         .await;
+    info!("All reconciles finished, shut down.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_changes_filtered() {
+        let mut user = fixtures::user("app", serde_json::json!({"metadata": {"generation": 1}}));
+        let before = user_changed(&user);
+        let mut status = user.status.clone().unwrap_or_default();
+        status.set_err(
+            "2024-01-01T00:00:00Z",
+            &OperatorError::Validation("x".to_string()),
+        );
+        user.status = Some(status);
+        assert_eq!(user_changed(&user), before);
+
+        user.metadata.generation = Some(2);
+        assert_ne!(user_changed(&user), before);
+        let before = user_changed(&user);
+        user.metadata.finalizers = Some(vec!["eeops.io/finalizer".to_string()]);
+        assert_ne!(user_changed(&user), before);
+    }
+
+    #[test]
+    fn namespaces_filtered() {
+        let namespaces = env::Namespaces {
+            watch: vec!["*".to_string()],
+            exclude: vec!["sandbox".to_string()],
+            ..Default::default()
+        };
+        assert!(namespaces.enabled("team-a", "operator"));
+        assert!(!namespaces.enabled("sandbox", "operator"));
+        let own = env::Namespaces::default();
+        assert!(own.enabled("operator", "operator"));
+        assert!(!own.enabled("team-a", "operator"));
+        assert!(!own.other_than("operator"));
+        // Each namespace in exactly one shard
+        for namespace in ["team-a", "team-b", "team-c", "team-d"] {
+            let shards = (0..3).filter(|&shard| {
+                let sharded = env::Namespaces {
+                    shards: 3,
+                    shard,
+                    ..namespaces.clone()
+                };
+                sharded.enabled(namespace, "operator")
+            });
+            assert_eq!(shards.count(), 1);
+        }
+
+        let secret = |namespace: &str| {
+            let mut secret = Secret::default();
+            secret.metadata.namespace = Some(namespace.to_string());
+            secret
+        };
+        let enabled = |namespace: &str| namespaces.enabled(namespace, "operator");
+        let event = watcher::Event::Restarted(vec![secret("team-a"), secret("sandbox")]);
+        match enabled_namespaces(event, enabled) {
+            Some(watcher::Event::Restarted(secrets)) => assert_eq!(secrets.len(), 1),
+            _ => panic!("restart dropped"),
+        }
+        assert!(enabled_namespaces(watcher::Event::Applied(secret("sandbox")), enabled).is_none());
+    }
 }
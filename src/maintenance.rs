@@ -0,0 +1,79 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use futures_util::StreamExt;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{runtime::watcher, Api};
+use log::{info, warn};
+
+use crate::{env::as_bool, Context};
+
+/// ConfigMap key toggling the maintenance mode.
+pub const MAINTENANCE_KEY: &str = "maintenance";
+
+/// While active, no mutations are made in Elasticsearch
+/// or to secrets. Status updates are still written.
+pub struct Maintenance {
+    active: AtomicBool,
+}
+
+impl Maintenance {
+    pub fn new(active: bool) -> Self {
+        Self {
+            active: AtomicBool::new(active),
+        }
+    }
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+    pub fn set_active(&self, active: bool) {
+        if self.active.swap(active, Ordering::Relaxed) != active {
+            if active {
+                info!("Maintenance mode enabled, pausing all mutations.");
+            } else {
+                info!("Maintenance mode disabled, resuming.");
+            }
+        }
+    }
+}
+
+/// Maintenance flag of the ConfigMap, a missing key means no maintenance.
+fn maintenance_flag(cm: &ConfigMap) -> Option<bool> {
+    match cm.data.as_ref().and_then(|data| data.get(MAINTENANCE_KEY)) {
+        None => Some(false),
+        Some(value) => as_bool(value),
+    }
+}
+
+/// Follow the maintenance flag of a ConfigMap.
+/// A deleted ConfigMap means no maintenance.
+pub async fn watch_maintenance(configmap_name: String, context: Arc<Context>) {
+    let api: Api<ConfigMap> = Api::default_namespaced(context.client.clone());
+    let config = watcher::Config::default().fields(&format!("metadata.name={}", configmap_name));
+    info!(
+        "Watching ConfigMap {} for maintenance mode.",
+        configmap_name
+    );
+    let mut events = watcher(api, config).boxed();
+    while let Some(event) = events.next().await {
+        let configmap = match event {
+            Ok(watcher::Event::Applied(cm)) => Some(cm),
+            Ok(watcher::Event::Deleted(_)) => None,
+            Ok(watcher::Event::Restarted(cms)) => cms.into_iter().next(),
+            Err(e) => {
+                warn!("Error watching ConfigMap {}: {}", configmap_name, e);
+                continue;
+            }
+        };
+        match configmap.as_ref().map(maintenance_flag) {
+            None => context.maintenance.set_active(false),
+            Some(Some(active)) => context.maintenance.set_active(active),
+            Some(None) => warn!(
+                "ConfigMap {} has an invalid {} value, expected true or false.",
+                configmap_name, MAINTENANCE_KEY
+            ),
+        }
+    }
+}
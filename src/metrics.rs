@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex};
+
+use kube::ResourceExt;
+
+use crate::{error::ErrorCode, ElasticsearchUser};
+
+/// Prometheus metrics, served as text by GET /metrics.
+/// Per-resource values are read from the controller cache when
+/// scraped, only the failure counters are kept here.
+pub struct Metrics {
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+/// Escape a label value of the text exposition format.
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            failures: Mutex::new(BTreeMap::new()),
+        }
+    }
+    pub fn record_failure(&self, code: ErrorCode) {
+        *self
+            .failures
+            .lock()
+            .expect("Metrics lock poisoned")
+            .entry(code.as_str())
+            .or_default() += 1;
+    }
+    pub(crate) fn render<'a>(
+        &self,
+        users: impl IntoIterator<Item = &'a ElasticsearchUser>,
+    ) -> String {
+        let mut ready = String::new();
+        let mut last_sync = String::new();
+        for user in users {
+            let labels = format!(
+                "namespace=\"{}\",name=\"{}\"",
+                label(&user.namespace().unwrap_or_default()),
+                label(&user.name_any())
+            );
+            let status = user.status.as_ref();
+            let ok = status.is_some_and(|s| s.ok);
+            let _ = writeln!(ready, "eeops_user_ready{{{}}} {}", labels, ok as u8);
+            let synced = status
+                .and_then(|s| s.last_successful_sync.as_deref())
+                .and_then(|t| humantime::parse_rfc3339(t).ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok());
+            if let Some(synced) = synced {
+                let _ = writeln!(
+                    last_sync,
+                    "eeops_user_last_successful_sync_timestamp_seconds{{{}}} {}",
+                    labels,
+                    synced.as_secs()
+                );
+            }
+        }
+        let mut out = String::new();
+        out.push_str("# HELP eeops_user_ready Whether the last reconciliation of the ElasticsearchUser succeeded.\n");
+        out.push_str("# TYPE eeops_user_ready gauge\n");
+        out.push_str(&ready);
+        out.push_str("# HELP eeops_user_last_successful_sync_timestamp_seconds Last reconciliation of the ElasticsearchUser without errors.\n");
+        out.push_str("# TYPE eeops_user_last_successful_sync_timestamp_seconds gauge\n");
+        out.push_str(&last_sync);
+        out.push_str(
+            "# HELP eeops_reconcile_failures_total Failed reconciliations by error code.\n",
+        );
+        out.push_str("# TYPE eeops_reconcile_failures_total counter\n");
+        for (reason, count) in self.failures.lock().expect("Metrics lock poisoned").iter() {
+            let _ = writeln!(
+                out,
+                "eeops_reconcile_failures_total{{reason=\"{}\"}} {}",
+                reason, count
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, status::ElasticSearchUserStatus};
+
+    #[test]
+    fn renders_gauges_and_counters() {
+        let mut user = fixtures::user("app", serde_json::json!({}));
+        user.status = Some(ElasticSearchUserStatus {
+            ok: true,
+            last_successful_sync: Some("2024-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        });
+        let metrics = Metrics::new();
+        metrics.record_failure(ErrorCode::Forbidden);
+        metrics.record_failure(ErrorCode::Forbidden);
+        let text = metrics.render([&user]);
+        assert!(text.contains("eeops_user_ready{namespace=\"default\",name=\"app\"} 1\n"));
+        assert!(text.contains(
+            "eeops_user_last_successful_sync_timestamp_seconds{namespace=\"default\",name=\"app\"} 1704067200\n"
+        ));
+        assert!(text.contains("eeops_reconcile_failures_total{reason=\"Forbidden\"} 2\n"));
+    }
+}
@@ -0,0 +1,450 @@
+//! Minimal fake of the Elasticsearch security API for local development,
+//! started by `--mock-elastic`. State is kept in memory only.
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{debug, error, info};
+use serde_json::{json, Value};
+
+use crate::http::{json, text};
+
+#[derive(Default)]
+struct MockState {
+    roles: BTreeMap<String, Value>,
+    /// Users including their password, which is never returned.
+    users: BTreeMap<String, Value>,
+    documents: usize,
+    /// Owner and whether it got invalidated, by API key ID.
+    api_keys: BTreeMap<String, (String, bool)>,
+    role_mappings: BTreeMap<String, Value>,
+    indices: BTreeMap<String, Value>,
+}
+
+/// Username and password of a basic auth header.
+fn basic_auth(req: &Request<Body>) -> Option<(String, String)> {
+    let encoded = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Managed users must log in with their password,
+/// any other login is accepted as superuser.
+fn authenticate(req: &Request<Body>, state: &MockState) -> Response<Body> {
+    let Some((username, password)) = basic_auth(req) else {
+        return json(
+            StatusCode::UNAUTHORIZED,
+            &json!({"error": "missing authentication"}),
+        );
+    };
+    match state.users.get(&username) {
+        Some(user)
+            if user["password"].as_str() == Some(password.as_str())
+                && user["enabled"].as_bool() != Some(false) =>
+        {
+            json(
+                StatusCode::OK,
+                &json!({"username": username, "roles": user["roles"]}),
+            )
+        }
+        Some(_) => json(
+            StatusCode::UNAUTHORIZED,
+            &json!({"error": "wrong password"}),
+        ),
+        None => json(
+            StatusCode::OK,
+            &json!({"username": username, "roles": ["superuser"]}),
+        ),
+    }
+}
+
+async fn read_json(req: Request<Body>) -> Result<Value, Response<Body>> {
+    let gzipped = req
+        .headers()
+        .get(hyper::header::CONTENT_ENCODING)
+        .is_some_and(|v| v == "gzip");
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .map_err(|e| text(StatusCode::BAD_REQUEST, e.to_string()))?;
+    if body.is_empty() {
+        return Ok(Value::Null);
+    }
+    if gzipped {
+        return serde_json::from_reader(flate2::read::GzDecoder::new(&body[..]))
+            .map_err(|e| text(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    serde_json::from_slice(&body).map_err(|e| text(StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+fn get(map: &BTreeMap<String, Value>, name: &str, hide_password: bool) -> Response<Body> {
+    match map.get(name) {
+        Some(value) => {
+            let mut value = value.clone();
+            if hide_password {
+                if let Some(object) = value.as_object_mut() {
+                    object.remove("password");
+                }
+            }
+            json(StatusCode::OK, &json!({ name: value }))
+        }
+        None => json(StatusCode::NOT_FOUND, &json!({})),
+    }
+}
+
+fn grant_api_key(state: &mut MockState, grant: &Value) -> Response<Body> {
+    let username = grant["username"].as_str().unwrap_or_default();
+    let valid = state
+        .users
+        .get(username)
+        .is_some_and(|user| user["password"] == grant["password"]);
+    if !valid {
+        return json(
+            StatusCode::UNAUTHORIZED,
+            &json!({"error": "wrong password"}),
+        );
+    }
+    let id = format!("mock-key-{}", state.api_keys.len());
+    let api_key = "mock-secret";
+    state
+        .api_keys
+        .insert(id.clone(), (username.to_string(), false));
+    json(
+        StatusCode::OK,
+        &json!({
+            "id": id,
+            "name": grant["api_key"]["name"],
+            "api_key": api_key,
+            "encoded": STANDARD.encode(format!("{}:{}", id, api_key)),
+        }),
+    )
+}
+
+/// Whether the field (or one of its values) starts with the prefix.
+fn has_prefix(field: &Value, prefix: &str) -> bool {
+    match field {
+        Value::String(value) => value.starts_with(prefix),
+        Value::Array(values) => values.iter().any(|v| has_prefix(v, prefix)),
+        _ => false,
+    }
+}
+
+/// Query API of users or roles, sorted by name and paginated by size
+/// and search_after. Of queries, only a single prefix query is supported.
+fn query_security(
+    map: &BTreeMap<String, Value>,
+    name_field: &str,
+    results_key: &str,
+    body: &Value,
+) -> Response<Body> {
+    let size = body["size"].as_u64().unwrap_or(10) as usize;
+    let after = body["search_after"][0].as_str().unwrap_or_default();
+    let prefix = body["query"]["prefix"]
+        .as_object()
+        .and_then(|p| p.iter().next());
+    let results: Vec<Value> = map
+        .iter()
+        .filter(|(name, _)| name.as_str() > after)
+        .filter(|(_, value)| match prefix {
+            Some((field, prefix)) => has_prefix(&value[field], prefix.as_str().unwrap_or_default()),
+            None => true,
+        })
+        .take(size)
+        .map(|(name, value)| {
+            let mut value = value.clone();
+            if let Some(object) = value.as_object_mut() {
+                object.remove("password");
+                object.insert(name_field.to_string(), name.as_str().into());
+                object.insert("_sort".to_string(), json!([name]));
+            }
+            value
+        })
+        .collect();
+    json(
+        StatusCode::OK,
+        &json!({"total": map.len(), "count": results.len(), results_key: results}),
+    )
+}
+
+fn delete(map: &mut BTreeMap<String, Value>, name: &str) -> Response<Body> {
+    match map.remove(name) {
+        Some(_) => json(StatusCode::OK, &json!({"found": true})),
+        None => json(StatusCode::NOT_FOUND, &json!({"found": false})),
+    }
+}
+
+async fn handle(
+    req: Request<Body>,
+    state: Arc<Mutex<MockState>>,
+) -> Result<Response<Body>, Infallible> {
+    debug!("Mock Elasticsearch: {} {}", req.method(), req.uri().path());
+    let method = req.method().clone();
+    let path: Vec<String> = req
+        .uri()
+        .path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(ToString::to_string)
+        .collect();
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    let query = req.uri().query().unwrap_or_default().to_string();
+    if let (&Method::GET, ["_security", "_authenticate"]) = (&method, path.as_slice()) {
+        let state = state.lock().expect("Mock state lock poisoned");
+        return Ok(authenticate(&req, &state));
+    }
+    let body = match method {
+        Method::PUT | Method::POST | Method::DELETE => match read_json(req).await {
+            Ok(body) => Some(body),
+            Err(res) => return Ok(res),
+        },
+        _ => None,
+    };
+    let mut state = state.lock().expect("Mock state lock poisoned");
+    let res = match (method, path.as_slice(), body) {
+        (Method::GET, [], _) => json(
+            StatusCode::OK,
+            &json!({"version": {"number": "8.12.2-mock"}}),
+        ),
+        (Method::GET, ["_cluster", "health"], _) => {
+            json(StatusCode::OK, &json!({"status": "green"}))
+        }
+        (Method::GET, ["_xpack"], _) => json(
+            StatusCode::OK,
+            &json!({"features": {"security": {"available": true, "enabled": true}}}),
+        ),
+        (Method::GET, ["_license"], _) => json(
+            StatusCode::OK,
+            &json!({"license": {"status": "active", "type": "trial"}}),
+        ),
+        (Method::GET, ["_security", "role"], _) => json(StatusCode::OK, &state.roles),
+        (Method::GET, ["_security", "role", name], _) => get(&state.roles, name, false),
+        (Method::PUT | Method::POST, ["_security", "role", name], Some(role)) => {
+            let created = state.roles.insert(name.to_string(), role).is_none();
+            json(StatusCode::OK, &json!({"role": {"created": created}}))
+        }
+        (Method::DELETE, ["_security", "role", name], _) => delete(&mut state.roles, name),
+        (Method::GET, ["_security", "role_mapping", name], _) => {
+            get(&state.role_mappings, name, false)
+        }
+        (Method::PUT | Method::POST, ["_security", "role_mapping", name], Some(mapping)) => {
+            let created = state
+                .role_mappings
+                .insert(name.to_string(), mapping)
+                .is_none();
+            json(
+                StatusCode::OK,
+                &json!({"role_mapping": {"created": created}}),
+            )
+        }
+        (Method::DELETE, ["_security", "role_mapping", name], _) => {
+            delete(&mut state.role_mappings, name)
+        }
+        (Method::POST, ["_security", "api_key", "grant"], Some(grant)) => {
+            grant_api_key(&mut state, &grant)
+        }
+        (Method::GET, ["_security", "api_key"], _) => {
+            let id = query.strip_prefix("id=").unwrap_or_default();
+            match state.api_keys.get(id) {
+                Some((owner, invalidated)) => json(
+                    StatusCode::OK,
+                    &json!({"api_keys": [{"id": id, "username": owner, "invalidated": invalidated}]}),
+                ),
+                None => json(StatusCode::NOT_FOUND, &json!({"api_keys": []})),
+            }
+        }
+        (Method::DELETE, ["_security", "api_key"], Some(query)) => {
+            let ids: Vec<&str> = query["ids"]
+                .as_array()
+                .map(|ids| ids.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let mut invalidated = 0;
+            for (id, (owner, is_invalidated)) in state.api_keys.iter_mut() {
+                if ids.contains(&id.as_str()) || query["username"].as_str() == Some(owner) {
+                    *is_invalidated = true;
+                    invalidated += 1;
+                }
+            }
+            json(
+                StatusCode::OK,
+                &json!({"invalidated_api_keys": invalidated}),
+            )
+        }
+        (Method::POST, ["_security", "_query", "user"], Some(body)) => {
+            query_security(&state.users, "username", "users", &body)
+        }
+        (Method::POST, ["_security", "_query", "role"], Some(body)) => {
+            query_security(&state.roles, "name", "roles", &body)
+        }
+        (Method::GET, ["_security", "user", name], _) => get(&state.users, name, true),
+        (Method::PUT | Method::POST, ["_security", "user", name], Some(mut user)) => {
+            // Like Elasticsearch, keep the password if none is given
+            if user.is_object() && user["password"].is_null() {
+                if let Some(password) = state.users.get(*name).map(|u| u["password"].clone()) {
+                    user["password"] = password;
+                }
+            }
+            let created = state.users.insert(name.to_string(), user).is_none();
+            json(StatusCode::OK, &json!({ "created": created }))
+        }
+        (Method::DELETE, ["_security", "user", name], _) => delete(&mut state.users, name),
+        (Method::PUT, ["_security", "user", name, "_disable"], _) => {
+            match state.users.get_mut(*name) {
+                Some(user) => {
+                    user["enabled"] = false.into();
+                    json(StatusCode::OK, &json!({}))
+                }
+                None => json(StatusCode::NOT_FOUND, &json!({})),
+            }
+        }
+        (Method::GET, ["_resolve", "index", pattern], _) => {
+            let prefix = pattern.trim_end_matches('*');
+            let indices: Vec<Value> = state
+                .indices
+                .keys()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| json!({ "name": name }))
+                .collect();
+            json(
+                StatusCode::OK,
+                &json!({"indices": indices, "aliases": [], "data_streams": []}),
+            )
+        }
+        (Method::PUT, [index], Some(body)) if !index.starts_with('_') => {
+            if state.indices.contains_key(*index) {
+                json(
+                    StatusCode::BAD_REQUEST,
+                    &json!({"error": {"type": "resource_already_exists_exception"}}),
+                )
+            } else {
+                state.indices.insert(index.to_string(), body);
+                json(
+                    StatusCode::OK,
+                    &json!({"acknowledged": true, "index": index}),
+                )
+            }
+        }
+        (Method::POST, [_index, "_doc"], Some(_)) => {
+            state.documents += 1;
+            json(
+                StatusCode::CREATED,
+                &json!({"_id": state.documents.to_string(), "result": "created"}),
+            )
+        }
+        _ => text(StatusCode::NOT_FOUND, "not supported by the mock"),
+    };
+    Ok(res)
+}
+
+/// Bind the mock to a free local port and serve it in the background.
+/// Returns the URL to use as ELASTIC_URL.
+pub fn start() -> Result<String, String> {
+    let state = Arc::new(Mutex::new(MockState::default()));
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, state.clone()))) }
+    });
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    let server = Server::try_bind(&addr)
+        .map_err(|e| format!("Could not bind mock Elasticsearch: {}", e))?
+        .serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    info!("Mock Elasticsearch listening on {}", url);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("Mock Elasticsearch stopped: {}", e);
+        }
+    });
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elasticsearch::{
+        ConnectionConfig, ElasticAdmin, ElasticError, ElasticsearchApi, Role,
+    };
+
+    #[tokio::test]
+    async fn compatible_with_elastic_admin() {
+        let elastic = ElasticAdmin::new(
+            &start().unwrap(),
+            "elastic",
+            "mock",
+            ConnectionConfig::default(),
+        );
+        elastic.connection_ok().await.unwrap();
+        assert_eq!(elastic.security_unavailable().await.unwrap(), None);
+        assert_eq!(
+            elastic.cluster_health().await.unwrap().as_deref(),
+            Some("green")
+        );
+
+        let role = Role {
+            indices: vec![],
+            global: None,
+            metadata: Default::default(),
+            unmanaged: Default::default(),
+        };
+        elastic.create_role("role-app", &role).await.unwrap();
+        assert_eq!(
+            elastic.get_role("role-app").await.unwrap(),
+            Some(role.clone())
+        );
+
+        let user = crate::elasticsearch::User {
+            password: Some("pw".into()),
+            roles: vec!["role-app".into()],
+            ..Default::default()
+        };
+        elastic.create_user("app", &user).await.unwrap();
+        assert!(elastic.check_login("app", &"pw".into()).await.is_ok());
+        let users = elastic.list_users().await.unwrap();
+        assert_eq!(users.keys().collect::<Vec<_>>(), vec!["app"]);
+        assert_eq!(users["app"].password, None);
+        let tagged = crate::elasticsearch::User {
+            roles: vec!["role-tagged".into()],
+            metadata: Some([("eeops_namespace".to_string(), "default".into())].into()),
+            ..Default::default()
+        };
+        elastic.create_user("tagged", &tagged).await.unwrap();
+        let users = elastic
+            .list_operator_users("eeops_namespace", "role-")
+            .await
+            .unwrap();
+        assert_eq!(users.keys().collect::<Vec<_>>(), vec!["tagged"]);
+        assert!(elastic.delete_user("tagged").await.unwrap());
+        let roles = elastic.list_roles().await.unwrap();
+        assert_eq!(roles.keys().collect::<Vec<_>>(), vec!["role-app"]);
+        assert!(roles["role-app"].get("name").is_none());
+        assert!(matches!(
+            elastic.check_login("app", &"wrong".into()).await,
+            Err(ElasticError::WrongCredentials)
+        ));
+        let api_key = elastic
+            .grant_api_key("app", &"pw".into(), "eeops-app", "role-app", &role)
+            .await
+            .unwrap();
+        assert!(elastic.api_key_valid(&api_key.id).await.unwrap());
+        ElasticsearchApi::invalidate_user_api_keys(&elastic, "app")
+            .await
+            .unwrap();
+        assert!(!elastic.api_key_valid(&api_key.id).await.unwrap());
+        assert!(elastic.delete_user("app").await.unwrap());
+        assert!(elastic.get_user("app").await.unwrap().is_none());
+        assert!(elastic.delete_role("role-app").await.unwrap());
+    }
+}
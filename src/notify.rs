@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use kube::ResourceExt;
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::ElasticsearchUser;
+
+/// Posts reconciliation failures to an optional webhook.
+pub struct Notifier {
+    url: Option<String>,
+    client: reqwest::Client,
+    cluster: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Notification<'a> {
+    /// Human readable summary, shown by Slack compatible webhooks.
+    text: String,
+    event: &'a str,
+    name: String,
+    namespace: String,
+    error: &'a str,
+    cluster: &'a str,
+    consecutive_failures: u32,
+}
+
+impl Notifier {
+    pub fn new(url: Option<String>, cluster: impl ToString) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_millis(5_000))
+                .build()
+                .expect("Unexpected error in building HTTP Client"),
+            cluster: cluster.to_string(),
+        }
+    }
+    /// Notify about a failed reconciliation. Only logs
+    /// if the webhook can't be reached.
+    pub(crate) async fn reconcile_failed(
+        &self,
+        user: &ElasticsearchUser,
+        error: &str,
+        consecutive_failures: u32,
+        degraded: bool,
+    ) {
+        let Some(url) = &self.url else {
+            return;
+        };
+        let event = if degraded {
+            "Degraded"
+        } else {
+            "ReconcileFailed"
+        };
+        let namespace = user.namespace().unwrap_or_default();
+        let notification = Notification {
+            text: format!(
+                "ElasticsearchUser {}/{} {} ({} failures, cluster {}): {}",
+                namespace,
+                user.name_any(),
+                if degraded {
+                    "is degraded"
+                } else {
+                    "failed to reconcile"
+                },
+                consecutive_failures,
+                self.cluster,
+                error
+            ),
+            event,
+            name: user.name_any(),
+            namespace,
+            error,
+            cluster: &self.cluster,
+            consecutive_failures,
+        };
+        match self.client.post(url).json(&notification).send().await {
+            Ok(res) if res.status().is_success() => {
+                debug!("Sent {} notification for {}", event, user.name_any())
+            }
+            Ok(res) => warn!("Notification webhook responded with {}", res.status()),
+            Err(e) => warn!("Could not send notification: {}", e),
+        }
+    }
+}
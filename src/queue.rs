@@ -0,0 +1,203 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use log::info;
+use tokio::sync::Notify;
+
+/// Reconciles waiting for a slot from which on they are logged as a batch,
+/// e.g. after a config change touching every role.
+const BATCH_THRESHOLD: usize = 50;
+/// Finished reconciles of a batch between progress logs.
+const BATCH_PROGRESS: usize = 100;
+
+/// Limits the reconciles running against Elasticsearch at once, with two
+/// tiers: new and edited ElasticsearchUsers and deletions go ahead of the
+/// periodic re-checks, which only start while no priority work is waiting.
+/// Many reconciles waiting at once are logged as a batch with progress.
+pub struct WorkQueue {
+    limit: usize,
+    state: Mutex<QueueState>,
+    released: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    waiting: usize,
+    waiting_priority: usize,
+    batch: Option<Batch>,
+}
+
+/// Reconciles queued up while the threshold was exceeded.
+struct Batch {
+    started: Instant,
+    done: usize,
+}
+
+impl QueueState {
+    fn wait(&mut self, priority: bool, limit: usize) {
+        self.waiting += 1;
+        if priority {
+            self.waiting_priority += 1;
+        }
+        if self.waiting >= BATCH_THRESHOLD && self.batch.is_none() {
+            info!(
+                "{} reconciles waiting, apply them {} at a time.",
+                self.waiting, limit
+            );
+            self.batch = Some(Batch {
+                started: Instant::now(),
+                done: 0,
+            });
+        }
+    }
+    fn finish(&mut self) {
+        self.running -= 1;
+        let Some(batch) = self.batch.as_mut() else {
+            return;
+        };
+        batch.done += 1;
+        if self.waiting == 0 && self.running == 0 {
+            info!(
+                "Batch of {} reconciles finished in {}s.",
+                batch.done,
+                batch.started.elapsed().as_secs()
+            );
+            self.batch = None;
+        } else if batch.done % BATCH_PROGRESS == 0 {
+            info!(
+                "Batch progress: {} reconciles done, {} waiting.",
+                batch.done, self.waiting
+            );
+        }
+    }
+}
+
+/// Slot in the queue, freed on drop.
+pub struct Permit {
+    queue: Arc<WorkQueue>,
+}
+
+impl WorkQueue {
+    /// A limit of 0 runs everything right away.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(QueueState::default()),
+            released: Notify::new(),
+        }
+    }
+    fn try_acquire(&self, priority: bool) -> bool {
+        let mut state = self.state.lock().expect("Queue lock poisoned");
+        let free = self.limit == 0 || state.running < self.limit;
+        if free && (priority || state.waiting_priority == 0) {
+            state.running += 1;
+            return true;
+        }
+        false
+    }
+    /// Wait for a free slot.
+    pub async fn acquire(self: &Arc<Self>, priority: bool) -> Permit {
+        let _waiter = Waiter::new(self, priority);
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            // Register before checking, to not miss a release in between
+            released.as_mut().enable();
+            if self.try_acquire(priority) {
+                return Permit {
+                    queue: self.clone(),
+                };
+            }
+            released.await;
+        }
+    }
+}
+
+/// Counts a waiting reconcile, also if it is cancelled.
+struct Waiter<'a> {
+    queue: &'a WorkQueue,
+    priority: bool,
+}
+
+impl<'a> Waiter<'a> {
+    fn new(queue: &'a WorkQueue, priority: bool) -> Self {
+        queue
+            .state
+            .lock()
+            .expect("Queue lock poisoned")
+            .wait(priority, queue.limit);
+        Self { queue, priority }
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().expect("Queue lock poisoned");
+        state.waiting -= 1;
+        if self.priority {
+            state.waiting_priority -= 1;
+        }
+        drop(state);
+        self.queue.released.notify_waiters();
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().expect("Queue lock poisoned");
+        state.finish();
+        drop(state);
+        self.queue.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn priority_first() {
+        let queue = Arc::new(WorkQueue::new(1));
+        let running = queue.acquire(false).await;
+        let routine = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire(false).await }
+        });
+        let fresh = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire(true).await }
+        });
+        tokio::task::yield_now().await;
+        drop(running);
+        let fresh = fresh.await.unwrap();
+        assert!(!routine.is_finished());
+        drop(fresh);
+        routine.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn batch_ends_when_drained() {
+        let queue = Arc::new(WorkQueue::new(1));
+        let running = queue.acquire(false).await;
+        let waiting: Vec<_> = (0..BATCH_THRESHOLD)
+            .map(|_| {
+                let queue = queue.clone();
+                tokio::spawn(async move { drop(queue.acquire(false).await) })
+            })
+            .collect();
+        while queue.state.lock().unwrap().waiting < BATCH_THRESHOLD {
+            tokio::task::yield_now().await;
+        }
+        assert!(queue.state.lock().unwrap().batch.is_some());
+        drop(running);
+        for task in waiting {
+            task.await.unwrap();
+        }
+        let state = queue.state.lock().unwrap();
+        assert!(state.batch.is_none());
+        assert_eq!((state.running, state.waiting), (0, 0));
+    }
+}
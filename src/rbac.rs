@@ -0,0 +1,265 @@
+use k8s_openapi::{
+    api::rbac::v1::{
+        ClusterRole, ClusterRoleBinding, PolicyRule, Role, RoleBinding, RoleRef, Subject,
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+};
+use kube::CustomResourceExt;
+use serde::Serialize;
+
+use crate::{
+    class::ElasticsearchUserClass, cluster_user::ClusterElasticsearchUser, env::Env,
+    group::ElasticsearchUserGroup, ElasticsearchUser,
+};
+
+const RBAC_GROUP: &str = "rbac.authorization.k8s.io";
+
+fn rule(group: &str, resources: &[&str], verbs: &[&str], names: &[&str]) -> PolicyRule {
+    let strings = |v: &[&str]| v.iter().map(ToString::to_string).collect::<Vec<_>>();
+    PolicyRule {
+        api_groups: Some(vec![group.to_string()]),
+        resources: Some(strings(resources)),
+        verbs: strings(verbs),
+        resource_names: (!names.is_empty()).then(|| strings(names)),
+        ..Default::default()
+    }
+}
+
+fn meta(name: &str, namespace: Option<&str>) -> ObjectMeta {
+    ObjectMeta {
+        name: Some(name.to_string()),
+        namespace: namespace.map(ToString::to_string),
+        ..Default::default()
+    }
+}
+
+/// Rules within the operator namespace.
+fn namespaced_rules(env: &Env) -> Vec<PolicyRule> {
+    let group = ElasticsearchUser::api_resource().group;
+    let mut rules = vec![
+        // Created and deleted for the members of ElasticsearchUserGroups
+        rule(
+            &group,
+            &["elasticsearchusers"],
+            &["get", "list", "watch", "create", "patch", "delete"],
+            &[],
+        ),
+        rule(
+            &group,
+            &["elasticsearchusergroups"],
+            &["get", "list", "watch"],
+            &[],
+        ),
+        rule(&group, &["elasticsearchusergroups/status"], &["patch"], &[]),
+        // Owner references of the members block the deletion of the group
+        rule(
+            &group,
+            &["elasticsearchusergroups/finalizers"],
+            &["update"],
+            &[],
+        ),
+        rule(
+            &group,
+            &["elasticsearchusers/status"],
+            &["get", "patch"],
+            &[],
+        ),
+        // Owner references of the secrets block the deletion of the CR
+        rule(&group, &["elasticsearchusers/finalizers"], &["update"], &[]),
+        rule(
+            "",
+            &["secrets"],
+            &["get", "list", "watch", "create", "patch", "delete"],
+            &[],
+        ),
+        rule("events.k8s.io", &["events"], &["create"], &[]),
+    ];
+    if let Some(name) = &env.maintenance_configmap {
+        rules.push(rule(
+            "",
+            &["configmaps"],
+            &["get", "list", "watch"],
+            &[name],
+        ));
+    }
+    let names: Vec<String> = env
+        .status_configmap
+        .iter()
+        .chain(&env.namespace_status_configmap)
+        .flat_map(|name| env.namespaces.shard_names(name))
+        .collect();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    if !names.is_empty() {
+        // Names can't restrict create
+        rules.push(rule("", &["configmaps"], &["create"], &[]));
+        rules.push(rule("", &["configmaps"], &["get", "patch"], &names));
+    }
+    rules
+}
+
+/// Cluster-wide rules, see MANAGE_CRDS, CLUSTER_USERS, WATCH_NAMESPACES,
+/// spec.secretNamespace and spec.classRef.
+fn cluster_rules(env: &Env, namespace: &str) -> Vec<PolicyRule> {
+    let mut rules = vec![
+        rule("", &["namespaces"], &["get"], &[]),
+        rule(
+            &ElasticsearchUserClass::api_resource().group,
+            &["elasticsearchuserclasses"],
+            &["get", "list", "watch"],
+            &[],
+        ),
+    ];
+    if env.manage_crds {
+        rules.push(rule(
+            "apiextensions.k8s.io",
+            &["customresourcedefinitions"],
+            &["create"],
+            &[],
+        ));
+        rules.push(rule(
+            "apiextensions.k8s.io",
+            &["customresourcedefinitions"],
+            &["patch"],
+            &[
+                ElasticsearchUser::crd_name(),
+                ElasticsearchUserGroup::crd_name(),
+                ElasticsearchUserClass::crd_name(),
+                ClusterElasticsearchUser::crd_name(),
+            ],
+        ));
+    }
+    if env.namespaces.other_than(namespace) {
+        let group = ElasticsearchUser::api_resource().group;
+        rules.push(rule(
+            &group,
+            &["elasticsearchusers"],
+            &["get", "list", "watch", "patch"],
+            &[],
+        ));
+        rules.push(rule(
+            &group,
+            &["elasticsearchusers/status"],
+            &["get", "patch"],
+            &[],
+        ));
+        rules.push(rule(
+            &group,
+            &["elasticsearchusers/finalizers"],
+            &["update"],
+            &[],
+        ));
+        rules.push(rule(
+            "",
+            &["secrets"],
+            &["get", "list", "watch", "create", "patch", "delete"],
+            &[],
+        ));
+        rules.push(rule("events.k8s.io", &["events"], &["create"], &[]));
+    }
+    if env.cluster_users {
+        let group = ClusterElasticsearchUser::api_resource().group;
+        rules.push(rule(
+            &group,
+            &["clusterelasticsearchusers"],
+            &["get", "list", "watch"],
+            &[],
+        ));
+        rules.push(rule(
+            &group,
+            &["clusterelasticsearchusers/status"],
+            &["patch"],
+            &[],
+        ));
+        // Owner references of the ElasticsearchUsers block the deletion
+        rules.push(rule(
+            &group,
+            &["clusterelasticsearchusers/finalizers"],
+            &["update"],
+            &[],
+        ));
+    }
+    rules
+}
+
+/// Rules in namespaces receiving secrets, see spec.secretNamespace.
+fn secret_writer_rules(env: &Env) -> Vec<PolicyRule> {
+    let mut rules = vec![rule(
+        "",
+        &["secrets"],
+        &["get", "create", "patch", "delete"],
+        &[],
+    )];
+    if let Some(name) = &env.namespace_status_configmap {
+        let names = env.namespaces.shard_names(name);
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        rules.push(rule("", &["configmaps"], &["create"], &[]));
+        rules.push(rule("", &["configmaps"], &["get", "patch"], &names));
+    }
+    rules
+}
+
+fn document(comment: &str, resource: &impl Serialize) -> String {
+    let yaml = serde_yaml::to_string(resource).expect("Serde YAML failed to serialize RBAC");
+    format!("---\n# {}\n{}", comment, yaml)
+}
+
+/// Role, ClusterRoles and bindings for the service account, as required
+/// by the configuration.
+pub fn manifests(env: &Env, service_account: &str, namespace: &str) -> String {
+    let subjects = Some(vec![Subject {
+        kind: "ServiceAccount".to_string(),
+        name: service_account.to_string(),
+        namespace: Some(namespace.to_string()),
+        ..Default::default()
+    }]);
+    let role = Role {
+        metadata: meta(service_account, Some(namespace)),
+        rules: Some(namespaced_rules(env)),
+    };
+    let role_binding = RoleBinding {
+        metadata: meta(service_account, Some(namespace)),
+        role_ref: RoleRef {
+            api_group: RBAC_GROUP.to_string(),
+            kind: "Role".to_string(),
+            name: service_account.to_string(),
+        },
+        subjects: subjects.clone(),
+    };
+    let cluster_role = ClusterRole {
+        metadata: meta(service_account, None),
+        rules: Some(cluster_rules(env, namespace)),
+        ..Default::default()
+    };
+    let cluster_role_binding = ClusterRoleBinding {
+        metadata: meta(service_account, None),
+        role_ref: RoleRef {
+            api_group: RBAC_GROUP.to_string(),
+            kind: "ClusterRole".to_string(),
+            name: service_account.to_string(),
+        },
+        subjects,
+    };
+    let secret_writer = ClusterRole {
+        metadata: meta(&format!("{}-secrets", service_account), None),
+        rules: Some(secret_writer_rules(env)),
+        ..Default::default()
+    };
+    [
+        document("ElasticsearchUsers, secrets and events", &role),
+        document("Binding of the Role", &role_binding),
+        document(
+            if env.manage_crds {
+                "Installing the CRDs, namespaces for spec.secretNamespace and ElasticsearchUserClasses"
+            } else {
+                "Namespaces for spec.secretNamespace and ElasticsearchUserClasses"
+            },
+            &cluster_role,
+        ),
+        document("Binding of the ClusterRole", &cluster_role_binding),
+        document(
+            "Only for spec.secretNamespace: bind with a RoleBinding in each target namespace",
+            &secret_writer,
+        ),
+    ]
+    .concat()
+}
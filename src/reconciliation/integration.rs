@@ -0,0 +1,114 @@
+//! End-to-end tests against a real Elasticsearch started via testcontainers.
+//! Run with `cargo test --features integration`, requires docker.
+use std::time::Duration;
+
+use testcontainers::{clients::Cli, core::WaitFor, GenericImage};
+
+use super::*;
+use crate::elasticsearch::ConnectionConfig;
+use crate::fixtures;
+
+const IMAGE: &str = "docker.elastic.co/elasticsearch/elasticsearch";
+const TAG: &str = "8.12.2";
+const ADMIN_PASSWORD: &str = "integration-password";
+
+fn elasticsearch_image() -> GenericImage {
+    GenericImage::new(IMAGE, TAG)
+        .with_env_var("discovery.type", "single-node")
+        .with_env_var("xpack.security.enabled", "true")
+        .with_env_var("xpack.security.http.ssl.enabled", "false")
+        .with_env_var("ELASTIC_PASSWORD", ADMIN_PASSWORD)
+        .with_env_var("ES_JAVA_OPTS", "-Xms512m -Xmx512m")
+        .with_exposed_port(9200)
+        .with_wait_for(WaitFor::message_on_stdout("started"))
+}
+
+async fn connect(port: u16) -> ElasticAdmin {
+    let elastic = ElasticAdmin::new(
+        &format!("http://127.0.0.1:{}", port),
+        "elastic",
+        ADMIN_PASSWORD,
+        ConnectionConfig::default(),
+    );
+    // The security index may still be initializing after "started"
+    for _ in 0..60 {
+        if elastic.connection_ok().await.is_ok() {
+            return elastic;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    panic!("Elasticsearch did not become ready");
+}
+
+fn es_user(name: &str, username: &str) -> ElasticsearchUser {
+    fixtures::user(
+        name,
+        serde_json::json!({"spec": {
+            "secretRef": format!("{}-secret", name),
+            "username": username,
+            "prefixes": ["logs-"],
+            "permissions": "Write",
+        }}),
+    )
+}
+
+#[tokio::test]
+async fn apply_rotate_cleanup() {
+    let docker = Cli::default();
+    let container = docker.run(elasticsearch_image());
+    let elastic = connect(container.get_host_port_ipv4(9200)).await;
+    let audit = AuditLog::new(None);
+    let app = es_user("app", "app");
+    let other = es_user("other", "app");
+
+    // Create
+    let outcome = apply_elastic(&app, "app", &"first-password".into(), &elastic, &audit)
+        .await
+        .unwrap();
+    assert!(outcome.user_created);
+    let role = elastic.get_role("role-app").await.unwrap().unwrap();
+    assert_eq!(role.indices, desired_role(&app).indices);
+    assert!(elastic
+        .check_login("app", &"first-password".into())
+        .await
+        .is_ok());
+
+    // Idempotent
+    let outcome = apply_elastic(&app, "app", &"first-password".into(), &elastic, &audit)
+        .await
+        .unwrap();
+    assert!(!outcome.user_created);
+    assert!(!outcome.password_rotated);
+
+    // Rotation
+    let outcome = apply_elastic(&app, "app", &"second-password".into(), &elastic, &audit)
+        .await
+        .unwrap();
+    assert!(outcome.password_rotated);
+    assert!(matches!(
+        elastic.check_login("app", &"first-password".into()).await,
+        Err(ElasticError::WrongCredentials)
+    ));
+    assert!(elastic
+        .check_login("app", &"second-password".into())
+        .await
+        .is_ok());
+
+    // Shared role survives the first cleanup
+    apply_elastic(&other, "app", &"second-password".into(), &elastic, &audit)
+        .await
+        .unwrap();
+    assert!(cleanup_user(&app, CleanupMode::Delete, &elastic, &audit)
+        .await
+        .unwrap());
+    let role = elastic.get_role("role-app").await.unwrap().unwrap();
+    assert!(!role.references().contains("default/app"));
+    assert!(role.references().contains("default/other"));
+
+    // Last reference removes the role
+    cleanup_user(&other, CleanupMode::Delete, &elastic, &audit)
+        .await
+        .unwrap();
+    assert!(elastic.get_role("role-app").await.unwrap().is_none());
+    assert!(elastic.get_user("app").await.unwrap().is_none());
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use log::{error, info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{
+    audit::AuditLog,
+    cloudevents::CloudEventSink,
+    env::{load_env, Env},
+    notify::Notifier,
+    Context,
+};
+
+/// Configuration which can be reloaded at runtime via SIGHUP,
+/// together with the clients built from it.
+pub struct Settings {
+    pub env: Env,
+    pub notifier: Notifier,
+    pub cloud_events: CloudEventSink,
+    pub audit: AuditLog,
+}
+
+impl Settings {
+    pub fn new(env: Env) -> Self {
+        Self {
+            notifier: Notifier::new(env.notify_webhook_url.clone(), &env.url),
+            cloud_events: CloudEventSink::new(env.cloudevents_sink_url.clone(), &env.url),
+            audit: AuditLog::new(env.audit_index.clone()),
+            env,
+        }
+    }
+}
+
+/// Settings only applied on startup, which changed.
+fn restart_required(old: &Env, new: &Env) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if old.url != new.url {
+        changed.push("ELASTIC_URL");
+    }
+    if old.skip_tls_cert_verify != new.skip_tls_cert_verify {
+        changed.push("ELASTIC_SKIP_VERIFY");
+    }
+    if old.ca_certificate != new.ca_certificate || old.ca_in_secret != new.ca_in_secret {
+        changed.push("ELASTIC_CA_CERT_FILE/ELASTIC_CA_IN_SECRET");
+    }
+    if old.compression != new.compression {
+        changed.push("ELASTIC_COMPRESSION");
+    }
+    if old.pool_idle_timeout != new.pool_idle_timeout
+        || old.pool_max_idle_per_host != new.pool_max_idle_per_host
+        || old.tcp_keepalive != new.tcp_keepalive
+        || old.http2 != new.http2
+    {
+        changed.push("ELASTIC_POOL_*/ELASTIC_TCP_KEEPALIVE_SECONDS/ELASTIC_HTTP2");
+    }
+    if old.connect_timeout != new.connect_timeout || old.request_timeout != new.request_timeout {
+        changed.push("ELASTIC_CONNECT_TIMEOUT_SECONDS/ELASTIC_REQUEST_TIMEOUT_SECONDS");
+    }
+    if old.reconnect_after != new.reconnect_after {
+        changed.push("ELASTIC_RECONNECT_AFTER_FAILURES");
+    }
+    if old.instance_id != new.instance_id {
+        changed.push("OPERATOR_INSTANCE_ID");
+    }
+    if old.kibana_url != new.kibana_url {
+        changed.push("KIBANA_URL");
+    }
+    if old.watch_label_selector != new.watch_label_selector {
+        changed.push("WATCH_LABEL_SELECTOR");
+    }
+    if old.manage_crds != new.manage_crds {
+        changed.push("MANAGE_CRDS");
+    }
+    if old.cluster_users != new.cluster_users {
+        changed.push("CLUSTER_USERS");
+    }
+    if old.http_port != new.http_port
+        || old.api_token != new.api_token
+        || old.dashboard != new.dashboard
+    {
+        changed.push("HTTP_PORT/API_TOKEN/DASHBOARD");
+    }
+    if old.credentials_secret != new.credentials_secret {
+        changed.push("ELASTIC_CREDENTIALS_SECRET");
+    }
+    if old.maintenance_mode != new.maintenance_mode
+        || old.maintenance_configmap != new.maintenance_configmap
+    {
+        changed.push("MAINTENANCE_MODE/MAINTENANCE_CONFIGMAP");
+    }
+    if old.status_configmap != new.status_configmap
+        || old.namespace_status_configmap != new.namespace_status_configmap
+    {
+        changed.push("STATUS_CONFIGMAP/NAMESPACE_STATUS_CONFIGMAP");
+    }
+    if old.concurrency != new.concurrency || old.field_manager != new.field_manager {
+        changed.push("RECONCILE_CONCURRENCY/FIELD_MANAGER");
+    }
+    if old.namespaces != new.namespaces {
+        changed.push("WATCH_NAMESPACES/EXCLUDE_NAMESPACES/SHARDS");
+    }
+    if old.watch_stall != new.watch_stall {
+        changed.push("WATCH_STALL_SECONDS");
+    }
+    if old.sentry_dsn != new.sentry_dsn {
+        changed.push("SENTRY_DSN");
+    }
+    changed
+}
+
+/// Re-read the configuration file on SIGHUP. Log level, intervals,
+/// notification settings and the operator's credentials are applied
+/// without interrupting running reconciles.
+pub async fn watch_reload(
+    config_path: Option<String>,
+    mock_elastic_url: Option<String>,
+    context: Arc<Context>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Could not listen for SIGHUP, reload disabled: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        let mut env = match load_env(config_path.as_deref(), mock_elastic_url.clone()) {
+            Ok(env) => env,
+            Err(e) => {
+                error!("Reload failed, keep the current configuration: {}", e);
+                continue;
+            }
+        };
+        let current = context.settings();
+        let changed = restart_required(&current.env, &env);
+        if !changed.is_empty() {
+            warn!(
+                "Changes of {} require a restart, ignored until then.",
+                changed.join(", ")
+            );
+        }
+        if (&env.username, &env.password) != (&current.env.username, &current.env.password) {
+            let candidate = context
+                .elastic
+                .clone_with_new_login(&env.username, &env.password);
+            match candidate.connection_ok().await {
+                Ok(()) => {
+                    context.elastic.set_login(&env.username, &env.password);
+                    info!("Reloaded Elasticsearch credentials.");
+                }
+                Err(e) => {
+                    error!("New credentials are not working, keep the old ones: {}", e);
+                    env.username = current.env.username.clone();
+                    env.password = current.env.password.clone();
+                }
+            }
+        }
+        if let Some(level) = env.log_level {
+            log::set_max_level(level);
+        }
+        crate::elasticsearch::set_trace_http(env.trace_http);
+        context.set_settings(Settings::new(env));
+        info!("Configuration reloaded.");
+    }
+}
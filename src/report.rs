@@ -0,0 +1,320 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::reflector::Store,
+    Api, Client, ResourceExt,
+};
+use log::{debug, warn};
+
+use crate::{
+    field_manager, reconciliation::secrets_allowed, status::CONDITION_PAUSED, Context,
+    ElasticsearchUser,
+};
+
+/// Counts over all managed ElasticsearchUsers.
+#[derive(Default)]
+struct Summary {
+    managed: usize,
+    ready: usize,
+    failing: usize,
+    degraded: usize,
+    paused: usize,
+    /// Oldest last sync time, all users were reconciled since.
+    last_full_sweep: Option<String>,
+}
+
+fn summarize(users: &[Arc<ElasticsearchUser>], failure_threshold: u32) -> Summary {
+    let mut summary = Summary {
+        managed: users.len(),
+        ..Default::default()
+    };
+    let mut oldest_sync: Option<String> = None;
+    let mut never_synced = false;
+    for user in users {
+        let Some(status) = &user.status else {
+            never_synced = true;
+            continue;
+        };
+        if status.ok {
+            summary.ready += 1;
+        } else {
+            summary.failing += 1;
+        }
+        if failure_threshold > 0 && status.consecutive_failures >= failure_threshold {
+            summary.degraded += 1;
+        }
+        if status
+            .conditions
+            .iter()
+            .any(|c| c.type_ == CONDITION_PAUSED)
+        {
+            summary.paused += 1;
+        }
+        match &status.last_sync_time {
+            // RFC 3339 in UTC, lexical order is chronological
+            Some(t) if oldest_sync.as_ref().map_or(true, |o| t < o) => {
+                oldest_sync = Some(t.clone())
+            }
+            Some(_) => (),
+            None => never_synced = true,
+        }
+    }
+    if !never_synced {
+        summary.last_full_sweep = oldest_sync;
+    }
+    summary
+}
+
+fn now() -> String {
+    humantime::format_rfc3339_seconds(SystemTime::now()).to_string()
+}
+
+async fn apply_configmap(
+    api: &Api<ConfigMap>,
+    name: &str,
+    data: BTreeMap<String, String>,
+) -> Result<(), kube::Error> {
+    let mut configmap = ConfigMap::default();
+    configmap.metadata.name = Some(name.to_string());
+    configmap.data = Some(data);
+    let patch_params = PatchParams::apply(field_manager()).force();
+    api.patch(name, &patch_params, &Patch::Apply(&configmap))
+        .await?;
+    Ok(())
+}
+
+/// Periodically write a summary of all managed users into a ConfigMap,
+/// giving dashboards a single object to scrape.
+pub async fn publish_reports(
+    configmap_name: String,
+    store: Store<ElasticsearchUser>,
+    context: Arc<Context>,
+) {
+    let api: Api<ConfigMap> = Api::default_namespaced(context.client.clone());
+    loop {
+        let settings = context.settings();
+        let summary = summarize(&store.state(), settings.env.failure_threshold);
+        let version = match context.elastic.version().await {
+            Ok(version) => version,
+            Err(e) => {
+                debug!("Could not get Elasticsearch version: {}", e);
+                "unknown".to_string()
+            }
+        };
+        let data = BTreeMap::from([
+            ("managedUsers".to_string(), summary.managed.to_string()),
+            ("ready".to_string(), summary.ready.to_string()),
+            ("failing".to_string(), summary.failing.to_string()),
+            ("degraded".to_string(), summary.degraded.to_string()),
+            ("paused".to_string(), summary.paused.to_string()),
+            (
+                "lastFullSweep".to_string(),
+                summary.last_full_sweep.unwrap_or_default(),
+            ),
+            ("elasticsearchVersion".to_string(), version),
+            (
+                "securityError".to_string(),
+                context.security.error().unwrap_or_default(),
+            ),
+            ("updatedAt".to_string(), now()),
+        ]);
+        if let Err(e) = apply_configmap(&api, &configmap_name, data).await {
+            warn!("Could not publish status report {}: {}", configmap_name, e);
+        }
+        // Read on every iteration, as it may be reloaded
+        tokio::time::sleep(settings.env.status_report_interval).await;
+    }
+}
+
+/// Namespace receiving the credentials of an ElasticsearchUser.
+fn credentials_namespace(user: &ElasticsearchUser) -> String {
+    match &user.spec.secret_namespace {
+        Some(namespace) => namespace.clone(),
+        None => user.namespace().unwrap_or_default(),
+    }
+}
+
+/// ElasticsearchUsers by the namespace receiving their credentials,
+/// including the given namespaces even if none are left.
+fn by_namespace(
+    users: Vec<Arc<ElasticsearchUser>>,
+    namespaces: &BTreeSet<String>,
+) -> BTreeMap<String, Vec<Arc<ElasticsearchUser>>> {
+    let mut by_namespace: BTreeMap<String, Vec<Arc<ElasticsearchUser>>> = namespaces
+        .iter()
+        .map(|namespace| (namespace.clone(), Vec::new()))
+        .collect();
+    for user in users {
+        by_namespace
+            .entry(credentials_namespace(&user))
+            .or_default()
+            .push(user);
+    }
+    by_namespace
+}
+
+/// Report of the ElasticsearchUsers of one namespace, without
+/// operator-wide details.
+fn namespace_data(
+    users: &[Arc<ElasticsearchUser>],
+    failure_threshold: u32,
+) -> BTreeMap<String, String> {
+    let summary = summarize(users, failure_threshold);
+    let failing_users: Vec<String> = users
+        .iter()
+        .filter(|user| user.status.as_ref().is_some_and(|s| !s.ok))
+        .map(|user| {
+            format!(
+                "{}/{}",
+                user.namespace().unwrap_or_default(),
+                user.name_any()
+            )
+        })
+        .collect();
+    BTreeMap::from([
+        ("managedUsers".to_string(), summary.managed.to_string()),
+        ("ready".to_string(), summary.ready.to_string()),
+        ("failing".to_string(), summary.failing.to_string()),
+        ("degraded".to_string(), summary.degraded.to_string()),
+        ("paused".to_string(), summary.paused.to_string()),
+        (
+            "lastFullSweep".to_string(),
+            summary.last_full_sweep.unwrap_or_default(),
+        ),
+        ("failingUsers".to_string(), failing_users.join(",")),
+        ("updatedAt".to_string(), now()),
+    ])
+}
+
+/// Whether reports may be written into the namespace for the
+/// ElasticsearchUsers of `from`, as their secrets. Checks are cached in
+/// `grants` for one iteration.
+async fn report_allowed(
+    client: &Client,
+    grants: &mut BTreeMap<(String, String), bool>,
+    namespace: &str,
+    from: &str,
+) -> bool {
+    if namespace == from {
+        return true;
+    }
+    let key = (namespace.to_string(), from.to_string());
+    if let Some(allowed) = grants.get(&key) {
+        return *allowed;
+    }
+    let allowed = match secrets_allowed(client, namespace, from).await {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            warn!(
+                "Could not check namespace {} for status reports: {}",
+                namespace, e
+            );
+            false
+        }
+    };
+    if !allowed {
+        debug!(
+            "Namespace {} does not allow secrets from {}, skip status report",
+            namespace, from
+        );
+    }
+    grants.insert(key, allowed);
+    allowed
+}
+
+/// Periodically write a summary into every namespace receiving
+/// credentials, readable by its owners without access to the
+/// operator's namespace. Namespaces left without users get a
+/// final report with zero counts. Like the secrets, other namespaces
+/// than that of the ElasticsearchUser are only written if they allow
+/// it by eeops.io/allow-secrets-from.
+pub async fn publish_namespace_reports(
+    configmap_name: String,
+    store: Store<ElasticsearchUser>,
+    context: Arc<Context>,
+) {
+    // Namespaces written, with the namespace of their ElasticsearchUsers
+    let mut published: BTreeMap<String, String> = BTreeMap::new();
+    loop {
+        let settings = context.settings();
+        let mut grants = BTreeMap::new();
+        let mut users = Vec::new();
+        for user in store.state() {
+            let from = user.namespace().unwrap_or_default();
+            let namespace = credentials_namespace(&user);
+            if report_allowed(&context.client, &mut grants, &namespace, &from).await {
+                users.push(user);
+            }
+        }
+        let previous = std::mem::take(&mut published);
+        let reports = by_namespace(users, &previous.keys().cloned().collect());
+        for (namespace, users) in reports {
+            let from = match users.first() {
+                Some(user) => user.namespace().unwrap_or_default(),
+                None => previous[&namespace].clone(),
+            };
+            if users.is_empty()
+                && !report_allowed(&context.client, &mut grants, &namespace, &from).await
+            {
+                continue;
+            }
+            let api: Api<ConfigMap> = Api::namespaced(context.client.clone(), &namespace);
+            let data = namespace_data(&users, settings.env.failure_threshold);
+            let result = apply_configmap(&api, &configmap_name, data).await;
+            if let Err(e) = &result {
+                warn!(
+                    "Could not publish status report {} in namespace {}: {}",
+                    configmap_name, namespace, e
+                );
+            }
+            if !users.is_empty() || result.is_err() {
+                published.insert(namespace, from);
+            }
+        }
+        tokio::time::sleep(settings.env.status_report_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn user(name: &str, secret_namespace: Option<&str>, ok: bool) -> Arc<ElasticsearchUser> {
+        Arc::new(fixtures::user(
+            name,
+            serde_json::json!({
+                "metadata": {"namespace": "platform"},
+                "spec": {"secretNamespace": secret_namespace},
+                "status": {"ok": ok, "lastSyncTime": "2024-01-01T00:00:00Z"},
+            }),
+        ))
+    }
+
+    #[test]
+    fn reports_by_credentials_namespace() {
+        let users = vec![
+            user("a", None, true),
+            user("b", Some("team-a"), true),
+            user("c", Some("team-a"), false),
+        ];
+        let previous = BTreeSet::from(["team-b".to_string()]);
+        let reports = by_namespace(users, &previous);
+        assert_eq!(
+            reports.keys().collect::<Vec<_>>(),
+            vec!["platform", "team-a", "team-b"]
+        );
+        let data = namespace_data(&reports["team-a"], 0);
+        assert_eq!(data["managedUsers"], "2");
+        assert_eq!(data["ready"], "1");
+        assert_eq!(data["failingUsers"], "platform/c");
+        assert_eq!(data["lastFullSweep"], "2024-01-01T00:00:00Z");
+        assert_eq!(namespace_data(&reports["team-b"], 0)["managedUsers"], "0");
+    }
+}
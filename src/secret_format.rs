@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use reqwest::Url;
+
+use crate::{SecretFormat, SECRET_PASS, SECRET_URL, SECRET_USER};
+
+/// Provider written into Service Binding secrets.
+const SERVICE_BINDING_PROVIDER: &str = "eeops";
+
+impl SecretFormat {
+    /// The other formats, to find the credentials written before
+    /// the format was changed.
+    pub fn others(self) -> impl Iterator<Item = SecretFormat> {
+        [
+            SecretFormat::Default,
+            SecretFormat::ServiceBinding,
+            SecretFormat::Beats,
+        ]
+        .into_iter()
+        .filter(move |format| *format != self)
+    }
+    /// Keys written in the previous format only, removed after the
+    /// credentials were moved.
+    pub fn stale_keys(&self, previous: SecretFormat, url: &str) -> Vec<String> {
+        let current = self.connection_values(url);
+        previous
+            .connection_values(url)
+            .into_keys()
+            .chain([
+                previous.username_key().to_string(),
+                previous.password_key().to_string(),
+            ])
+            .filter(|key| {
+                key != self.username_key()
+                    && key != self.password_key()
+                    && !current.contains_key(key)
+            })
+            .collect()
+    }
+    pub fn username_key(&self) -> &'static str {
+        match self {
+            SecretFormat::Default => SECRET_USER,
+            SecretFormat::ServiceBinding => "username",
+            SecretFormat::Beats => "output.elasticsearch.username",
+        }
+    }
+    pub fn password_key(&self) -> &'static str {
+        match self {
+            SecretFormat::Default => SECRET_PASS,
+            SecretFormat::ServiceBinding => "password",
+            SecretFormat::Beats => "output.elasticsearch.password",
+        }
+    }
+    /// Secret type, only applied on creation as it is immutable.
+    pub fn secret_type(&self) -> Option<&'static str> {
+        match self {
+            SecretFormat::Default | SecretFormat::Beats => None,
+            SecretFormat::ServiceBinding => Some("servicebinding.io/elasticsearch"),
+        }
+    }
+    /// Connection values besides the credentials.
+    pub fn connection_values(&self, url: &str) -> BTreeMap<String, String> {
+        match self {
+            SecretFormat::Default => BTreeMap::from([(SECRET_URL.to_string(), url.to_string())]),
+            SecretFormat::ServiceBinding => {
+                let mut values = BTreeMap::from([
+                    ("type".to_string(), "elasticsearch".to_string()),
+                    ("provider".to_string(), SERVICE_BINDING_PROVIDER.to_string()),
+                    ("uri".to_string(), url.to_string()),
+                ]);
+                if let Ok(parsed) = Url::parse(url) {
+                    if let Some(host) = parsed.host_str() {
+                        values.insert("host".to_string(), host.to_string());
+                    }
+                    if let Some(port) = parsed.port_or_known_default() {
+                        values.insert("port".to_string(), port.to_string());
+                    }
+                }
+                values
+            }
+            SecretFormat::Beats => {
+                // JSON arrays are valid YAML flow sequences as well
+                let hosts = serde_json::json!([url]).to_string();
+                BTreeMap::from([
+                    ("output.elasticsearch.hosts".to_string(), hosts.clone()),
+                    ("ES_HOSTS".to_string(), hosts),
+                ])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_keys_of_previous_format() {
+        let url = "https://es:9200";
+        let stale = SecretFormat::ServiceBinding.stale_keys(SecretFormat::Default, url);
+        assert_eq!(stale, vec![SECRET_URL, SECRET_USER, SECRET_PASS]);
+        assert!(SecretFormat::Default
+            .stale_keys(SecretFormat::Default, url)
+            .is_empty());
+        assert_eq!(SecretFormat::Beats.others().count(), 2);
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::RwLock;
+
+use log::{error, info, warn};
+
+use crate::elasticsearch::ElasticAdmin;
+
+/// Whether the security features of Elasticsearch are enabled and
+/// licensed. Without, no users or roles can be provisioned.
+pub struct SecurityCheck {
+    /// Reason security is unavailable, None if available.
+    error: RwLock<Option<String>>,
+}
+
+impl SecurityCheck {
+    pub fn new() -> Self {
+        Self {
+            error: RwLock::new(None),
+        }
+    }
+    pub fn error(&self) -> Option<String> {
+        self.error.read().expect("Security lock poisoned").clone()
+    }
+    /// Probe _xpack and _license. Failing probes keep the previous state,
+    /// as they are no reason to block reconciliation.
+    pub async fn probe(&self, elastic: &ElasticAdmin) -> Option<String> {
+        let error = match elastic.security_unavailable().await {
+            Ok(error) => error,
+            Err(e) => {
+                warn!("Could not check license and security features: {}", e);
+                return self.error();
+            }
+        };
+        let mut current = self.error.write().expect("Security lock poisoned");
+        match (&*current, &error) {
+            (_, Some(reason)) if current.as_ref() != Some(reason) => {
+                error!("Users can't be provisioned: {}", reason)
+            }
+            (Some(_), None) => info!("Elasticsearch security is available again."),
+            _ => (),
+        }
+        *current = error.clone();
+        error
+    }
+}
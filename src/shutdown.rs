@@ -0,0 +1,44 @@
+use std::{future::Future, process::exit, sync::Arc};
+
+use log::{info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{http::HttpState, Context};
+
+/// Resolves on SIGTERM or SIGINT.
+pub async fn terminate() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+    tokio::select! {
+        _ = sigterm.recv() => (),
+        _ = tokio::signal::ctrl_c() => (),
+    }
+}
+
+/// Resolves once the operator is asked to terminate, which is when the
+/// controller stops starting new reconciles. In-flight ones get the grace
+/// period to finish their secret, user and role changes. The process exits
+/// after the grace period, or right away on a second signal.
+pub fn on_signal(http_state: Arc<HttpState>, context: Arc<Context>) -> impl Future<Output = ()> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        terminate().await;
+        let grace = context.settings().env.shutdown_grace;
+        info!(
+            "Shutting down, waiting up to {}s for in-flight reconciles.",
+            grace.as_secs()
+        );
+        http_state.set_ready(false);
+        let _ = tx.send(());
+        tokio::select! {
+            _ = terminate() => warn!("Terminated again, exit without waiting for reconciles."),
+            _ = tokio::time::sleep(grace) => warn!(
+                "In-flight reconciles did not finish within {}s, exit.",
+                grace.as_secs()
+            ),
+        }
+        exit(1);
+    });
+    async move {
+        let _ = rx.await;
+    }
+}
@@ -0,0 +1,393 @@
+use kube::{
+    api::{Patch, PatchParams},
+    Api, ResourceExt,
+};
+use log::debug;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    changes::Change,
+    elasticsearch::Role,
+    error::{ErrorCode, OperatorError},
+    reconciliation::ApplyOutcome,
+    ElasticsearchUser,
+};
+
+pub const CONDITION_READY: &str = "Ready";
+/// Set if the CR can't be reconciled without being changed.
+pub const CONDITION_STALLED: &str = "Stalled";
+/// Set while mutations are paused by the maintenance mode.
+pub const CONDITION_PAUSED: &str = "Paused";
+/// Set while mutations are skipped as the cluster health is red.
+pub const CONDITION_DEGRADED: &str = "Degraded";
+/// Set once spec.expiresAt passed and the user got removed.
+pub const CONDITION_EXPIRED: &str = "Expired";
+const STATUS_CONFLICT_RETRIES: u32 = 3;
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ElasticSearchUserStatus {
+    pub ok: bool,
+    pub error_message: Option<String>,
+    /// Classification of error_message, unset if ok.
+    pub error_code: Option<ErrorCode>,
+    /// Last reconciliation attempt, successful or not (RFC 3339).
+    pub last_sync_time: Option<String>,
+    /// Last reconciliation without errors (RFC 3339).
+    pub last_successful_sync: Option<String>,
+    /// Last time the password of the Elasticsearch user was set (RFC 3339).
+    pub password_last_rotated: Option<String>,
+    /// Username as last applied, e.g. read from spec.usernameFrom.
+    pub username: Option<String>,
+    /// Role as last applied to Elasticsearch.
+    pub applied_role: Option<AppliedRole>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// Failed reconciliations since the last successful one.
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// Retries of the failing reconciliation, i.e. the failures after
+    /// the first one. Reset on success.
+    #[serde(default)]
+    pub retry_count: u32,
+    /// Last time ok changed, i.e. since when the ElasticsearchUser is
+    /// failing or healthy (RFC 3339).
+    pub last_transition_time: Option<String>,
+    /// Hash of spec, secret version and Elasticsearch URL of the last
+    /// successful reconciliation, to skip unchanged periodic re-checks.
+    pub applied_hash: Option<String>,
+    /// Secret exposing the credentials, following the Provisioned
+    /// Service duck type of the Service Binding specification.
+    /// Only set with secretFormat serviceBinding.
+    pub binding: Option<Binding>,
+    /// Names of the additional secrets of spec.secrets last written,
+    /// to delete them once removed from the spec.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_outputs: Vec<String>,
+    /// Changes made in Elasticsearch by the last
+    /// reconciliation which changed anything.
+    pub last_applied_changes: Option<AppliedChanges>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedChanges {
+    /// RFC 3339
+    pub time: String,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Binding {
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedRole {
+    pub name: String,
+    indices: Vec<AppliedIndexPermission>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedIndexPermission {
+    names: Vec<String>,
+    privileges: Vec<String>,
+}
+
+/// Kubernetes style condition, e.g. Ready=True.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// "True" or "False"
+    pub status: String,
+    pub reason: Option<String>,
+    pub message: Option<String>,
+    /// Last change of the status field (RFC 3339).
+    pub last_transition_time: Option<String>,
+}
+
+impl AppliedRole {
+    pub(crate) fn new(name: impl ToString, role: &Role) -> Self {
+        Self {
+            name: name.to_string(),
+            indices: role
+                .indices
+                .iter()
+                .map(|index| AppliedIndexPermission {
+                    names: index.names.clone(),
+                    privileges: index
+                        .privileges
+                        .names()
+                        .into_iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ElasticSearchUserStatus {
+    /// Set ok, updating the transition time only if it changed.
+    fn set_ok_flag(&mut self, now: &str, ok: bool) {
+        if self.ok != ok || self.last_transition_time.is_none() {
+            self.last_transition_time = Some(now.to_string());
+        }
+        self.ok = ok;
+    }
+    pub fn set_ok(&mut self, now: &str, outcome: &ApplyOutcome) {
+        self.set_ok_flag(now, true);
+        self.error_message = None;
+        self.error_code = None;
+        self.consecutive_failures = 0;
+        self.retry_count = 0;
+        self.last_sync_time = Some(now.to_string());
+        self.last_successful_sync = Some(now.to_string());
+        if outcome.user_created || outcome.password_rotated {
+            self.password_last_rotated = Some(now.to_string());
+        }
+        self.applied_role = Some(AppliedRole::new(&outcome.role_name, &outcome.role));
+        if !outcome.changes.is_empty() {
+            self.last_applied_changes = Some(AppliedChanges {
+                time: now.to_string(),
+                changes: outcome.changes.clone(),
+            });
+        }
+        self.set_condition(now, CONDITION_READY, true, "Reconciled", None);
+        self.remove_condition(CONDITION_STALLED);
+        self.remove_condition(CONDITION_PAUSED);
+        self.remove_condition(CONDITION_DEGRADED);
+        self.remove_condition(CONDITION_EXPIRED);
+    }
+    pub fn set_err(&mut self, now: &str, error: &OperatorError) {
+        self.set_ok_flag(now, false);
+        self.error_message = Some(error.to_string());
+        self.error_code = Some(error.code());
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.retry_count = self.consecutive_failures - 1;
+        self.applied_hash = None;
+        self.last_sync_time = Some(now.to_string());
+        let reason = error.code().as_str();
+        self.set_condition(now, CONDITION_READY, false, reason, Some(error.to_string()));
+        if error.is_permanent() {
+            self.set_condition(
+                now,
+                CONDITION_STALLED,
+                true,
+                reason,
+                Some(error.to_string()),
+            );
+        } else {
+            self.remove_condition(CONDITION_STALLED);
+        }
+        self.remove_condition(CONDITION_PAUSED);
+        self.remove_condition(CONDITION_DEGRADED);
+    }
+    pub fn set_expired(&mut self, now: &str, expires_at: &str) {
+        let message = format!("Expired at {}", expires_at);
+        self.set_ok_flag(now, false);
+        self.error_message = None;
+        self.error_code = None;
+        self.consecutive_failures = 0;
+        self.retry_count = 0;
+        self.applied_hash = None;
+        self.last_sync_time = Some(now.to_string());
+        self.set_condition(
+            now,
+            CONDITION_READY,
+            false,
+            "Expired",
+            Some(message.clone()),
+        );
+        self.set_condition(now, CONDITION_EXPIRED, true, "Expired", Some(message));
+        self.remove_condition(CONDITION_STALLED);
+    }
+    /// Mark as paused by the maintenance mode, keeping lastSyncTime as
+    /// nothing was synced. Returns false if it was already paused before.
+    pub fn set_paused(&mut self, now: &str) -> bool {
+        let newly_paused = !self.conditions.iter().any(|c| c.type_ == CONDITION_PAUSED);
+        self.set_condition(
+            now,
+            CONDITION_PAUSED,
+            true,
+            "Maintenance",
+            Some("Mutations are paused by the maintenance mode".to_string()),
+        );
+        newly_paused
+    }
+    /// Mark as skipped due to the red cluster health, keeping lastSyncTime.
+    /// Returns false if it was already degraded before.
+    pub fn set_degraded(&mut self, now: &str) -> bool {
+        let newly_degraded = !self
+            .conditions
+            .iter()
+            .any(|c| c.type_ == CONDITION_DEGRADED);
+        self.set_condition(
+            now,
+            CONDITION_DEGRADED,
+            true,
+            "ClusterRed",
+            Some("Mutations are skipped while the cluster health is red".to_string()),
+        );
+        newly_degraded
+    }
+    /// Insert or update a condition. The transition time
+    /// is only updated if the status actually changed.
+    pub fn set_condition(
+        &mut self,
+        now: &str,
+        type_: &str,
+        status: bool,
+        reason: &str,
+        message: Option<String>,
+    ) {
+        let status = if status { "True" } else { "False" }.to_string();
+        match self.conditions.iter_mut().find(|c| c.type_ == type_) {
+            Some(condition) => {
+                if condition.status != status {
+                    condition.last_transition_time = Some(now.to_string());
+                }
+                condition.status = status;
+                condition.reason = Some(reason.to_string());
+                condition.message = message;
+            }
+            None => self.conditions.push(Condition {
+                type_: type_.to_string(),
+                status,
+                reason: Some(reason.to_string()),
+                message,
+                last_transition_time: Some(now.to_string()),
+            }),
+        }
+    }
+    pub fn remove_condition(&mut self, type_: &str) {
+        self.conditions.retain(|c| c.type_ != type_);
+    }
+}
+
+/// JSON merge patch turning `old` into `new`. Keys missing in `new`
+/// are set to null, as the merge would keep them otherwise.
+fn merge_patch(old: &Value, new: &Value) -> Value {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut patch: serde_json::Map<String, Value> = new
+                .iter()
+                .map(|(key, value)| {
+                    let old = old.get(key).unwrap_or(&Value::Null);
+                    (key.clone(), merge_patch(old, value))
+                })
+                .collect();
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                patch.insert(key.clone(), Value::Null);
+            }
+            Value::Object(patch)
+        }
+        (_, new) => new.clone(),
+    }
+}
+
+/// Update only the status subresource of the ElasticsearchUser, from its
+/// status as read to `status`. The patch is bound to the resourceVersion
+/// as read; on a conflict, the user is read again and the patch computed
+/// from its current status. Nothing is written if the status did not change.
+pub async fn patch_status(
+    api: &Api<ElasticsearchUser>,
+    user: &ElasticsearchUser,
+    status: &ElasticSearchUserStatus,
+) -> Result<(), kube::Error> {
+    let new = serde_json::to_value(status).expect("Serde JSON failed to serialize status");
+    let mut reread = None;
+    let mut attempt = 0;
+    loop {
+        let current = reread.as_ref().unwrap_or(user);
+        let old =
+            serde_json::to_value(&current.status).expect("Serde JSON failed to serialize status");
+        if old == new {
+            return Ok(());
+        }
+        let patch = Patch::Merge(serde_json::json!({
+            "metadata": { "resourceVersion": current.resource_version() },
+            "status": merge_patch(&old, &new),
+        }));
+        match api
+            .patch_status(&user.name_any(), &PatchParams::default(), &patch)
+            .await
+        {
+            Err(kube::Error::Api(ae)) if ae.code == 409 && attempt < STATUS_CONFLICT_RETRIES => {
+                attempt += 1;
+                debug!(
+                    "Conflict updating status of {}, retry ({}/{})",
+                    user.name_any(),
+                    attempt,
+                    STATUS_CONFLICT_RETRIES
+                );
+                reread = Some(api.get_status(&user.name_any()).await?);
+            }
+            result => return result.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_keys_nulled() {
+        let old = serde_json::json!({
+            "ok": false,
+            "errorMessage": "boom",
+            "secretOutputs": ["a"],
+            "appliedRole": {"cluster": ["monitor"], "indices": []}
+        });
+        let new = serde_json::json!({
+            "ok": true,
+            "errorMessage": null,
+            "appliedRole": {"indices": []}
+        });
+        assert_eq!(
+            merge_patch(&old, &new),
+            serde_json::json!({
+                "ok": true,
+                "errorMessage": null,
+                "secretOutputs": null,
+                "appliedRole": {"cluster": null, "indices": []}
+            })
+        );
+    }
+
+    #[test]
+    fn retries_reset_on_success() {
+        let error = OperatorError::Conflict("boom".to_string());
+        let outcome = ApplyOutcome {
+            user_created: false,
+            password_rotated: false,
+            role_name: "role".to_string(),
+            role: Role {
+                indices: vec![],
+                global: None,
+                metadata: Default::default(),
+                unmanaged: Default::default(),
+            },
+            changes: vec![],
+        };
+        let mut status = ElasticSearchUserStatus::default();
+        status.set_err("t1", &error);
+        status.set_err("t2", &error);
+        status.set_err("t3", &error);
+        assert_eq!(status.retry_count, 2);
+        assert_eq!(status.last_transition_time.as_deref(), Some("t1"));
+        status.set_ok("t4", &outcome);
+        assert_eq!(status.retry_count, 0);
+        assert_eq!(status.last_transition_time.as_deref(), Some("t4"));
+        status.set_ok("t5", &outcome);
+        assert_eq!(status.last_transition_time.as_deref(), Some("t4"));
+    }
+}